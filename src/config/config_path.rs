@@ -3,6 +3,10 @@ use std::ffi::OsString;
 
 static ZABRZE_CONFIG_HOME_ENV_KEY: &str = "ZABRZE_CONFIG_HOME";
 static XDG_CONFIG_HOME_ENV_KEY: &str = "XDG_CONFIG_HOME";
+static ZABRZE_CACHE_HOME_ENV_KEY: &str = "ZABRZE_CACHE_HOME";
+static XDG_CACHE_HOME_ENV_KEY: &str = "XDG_CACHE_HOME";
+static ZABRZE_STATE_HOME_ENV_KEY: &str = "ZABRZE_STATE_HOME";
+static XDG_STATE_HOME_ENV_KEY: &str = "XDG_STATE_HOME";
 static HOME_ENV_KEY: &str = "HOME";
 
 static DEFAULT_CONFIG_DIR: &str = "zabrze";
@@ -21,27 +25,67 @@ impl ConfigPath for ConfigPathImpl {
 }
 
 fn get_default_dir<C: ConfigPath>(c: &C) -> Option<String> {
-    // Return $ZABRZE_CONFIG_HOME if defined
-    if let Some(zabrze_config_home) = c.env(ZABRZE_CONFIG_HOME_ENV_KEY) {
-        return zabrze_config_home.to_str().map(String::from);
+    get_dir(
+        c,
+        ZABRZE_CONFIG_HOME_ENV_KEY,
+        XDG_CONFIG_HOME_ENV_KEY,
+        ".config",
+    )
+}
+
+fn get_default_cache_dir_impl<C: ConfigPath>(c: &C) -> Option<String> {
+    get_dir(
+        c,
+        ZABRZE_CACHE_HOME_ENV_KEY,
+        XDG_CACHE_HOME_ENV_KEY,
+        ".cache",
+    )
+}
+
+fn get_default_state_dir_impl<C: ConfigPath>(c: &C) -> Option<String> {
+    get_dir(
+        c,
+        ZABRZE_STATE_HOME_ENV_KEY,
+        XDG_STATE_HOME_ENV_KEY,
+        ".local/state",
+    )
+}
+
+fn get_dir<C: ConfigPath>(
+    c: &C,
+    zabrze_home_key: &str,
+    xdg_home_key: &str,
+    home_fallback: &str,
+) -> Option<String> {
+    // Return $ZABRZE_*_HOME if defined
+    if let Some(zabrze_home) = c.env(zabrze_home_key) {
+        return zabrze_home.to_str().map(String::from);
     }
 
-    // Get ${XDG_CONFIG_HOME:-$HOME/.config}
-    if let Some(xdg_config_home) = c.env(XDG_CONFIG_HOME_ENV_KEY) {
-        return xdg_config_home
+    // Get ${XDG_*_HOME:-$HOME/.*}
+    if let Some(xdg_home) = c.env(xdg_home_key) {
+        return xdg_home
             .to_str()
-            .map(|xdg_config_home| format!("{xdg_config_home}/{DEFAULT_CONFIG_DIR}"));
+            .map(|xdg_home| format!("{xdg_home}/{DEFAULT_CONFIG_DIR}"));
     }
 
     let home = c.env(HOME_ENV_KEY)?;
     home.to_str()
-        .map(|home| format!("{home}/.config/{DEFAULT_CONFIG_DIR}"))
+        .map(|home| format!("{home}/{home_fallback}/{DEFAULT_CONFIG_DIR}"))
 }
 
 pub fn get_default_config_dir() -> Option<String> {
     get_default_dir(&ConfigPathImpl {})
 }
 
+pub fn get_default_cache_dir() -> Option<String> {
+    get_default_cache_dir_impl(&ConfigPathImpl {})
+}
+
+pub fn get_default_state_dir() -> Option<String> {
+    get_default_state_dir_impl(&ConfigPathImpl {})
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -108,4 +152,108 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_cache_path() {
+        struct Scenario {
+            pub testname: &'static str,
+            pub envs: HashMap<&'static str, &'static str>,
+            pub expected: &'static str,
+        }
+
+        let scenarios = [
+            Scenario {
+                testname: "follow ZABRZE_CACHE_HOME",
+                envs: vec![
+                    ("ZABRZE_CACHE_HOME", "/home/user/.zabrze-cache"),
+                    ("XDG_CACHE_HOME", "/home/user/.xdgCache"),
+                    ("HOME", "/home/user"),
+                ]
+                .into_iter()
+                .collect(),
+                expected: "/home/user/.zabrze-cache",
+            },
+            Scenario {
+                testname: "follow XDG_CACHE_HOME",
+                envs: vec![
+                    ("ZABRZE_CONFIG_HOME", "/home/user/.zabrze"),
+                    ("XDG_CACHE_HOME", "/home/user/.xdgCache"),
+                    ("HOME", "/home/user"),
+                ]
+                .into_iter()
+                .collect(),
+                expected: "/home/user/.xdgCache/zabrze",
+            },
+            Scenario {
+                testname: "use default path",
+                envs: vec![("HOME", "/home/user")].into_iter().collect(),
+                expected: "/home/user/.cache/zabrze",
+            },
+        ];
+
+        for s in &scenarios {
+            let c = DummyConfigPath {
+                envs: s.envs.clone(),
+            };
+
+            assert_eq!(
+                get_default_cache_dir_impl(&c),
+                Some(s.expected.to_string()),
+                "{}",
+                s.testname
+            );
+        }
+    }
+
+    #[test]
+    fn test_state_path() {
+        struct Scenario {
+            pub testname: &'static str,
+            pub envs: HashMap<&'static str, &'static str>,
+            pub expected: &'static str,
+        }
+
+        let scenarios = [
+            Scenario {
+                testname: "follow ZABRZE_STATE_HOME",
+                envs: vec![
+                    ("ZABRZE_STATE_HOME", "/home/user/.zabrze-state"),
+                    ("XDG_STATE_HOME", "/home/user/.xdgState"),
+                    ("HOME", "/home/user"),
+                ]
+                .into_iter()
+                .collect(),
+                expected: "/home/user/.zabrze-state",
+            },
+            Scenario {
+                testname: "follow XDG_STATE_HOME",
+                envs: vec![
+                    ("ZABRZE_CONFIG_HOME", "/home/user/.zabrze"),
+                    ("XDG_STATE_HOME", "/home/user/.xdgState"),
+                    ("HOME", "/home/user"),
+                ]
+                .into_iter()
+                .collect(),
+                expected: "/home/user/.xdgState/zabrze",
+            },
+            Scenario {
+                testname: "use default path",
+                envs: vec![("HOME", "/home/user")].into_iter().collect(),
+                expected: "/home/user/.local/state/zabrze",
+            },
+        ];
+
+        for s in &scenarios {
+            let c = DummyConfigPath {
+                envs: s.envs.clone(),
+            };
+
+            assert_eq!(
+                get_default_state_dir_impl(&c),
+                Some(s.expected.to_string()),
+                "{}",
+                s.testname
+            );
+        }
+    }
 }
@@ -1,14 +1,15 @@
 pub mod config_path;
 pub mod snippet;
+pub mod template;
 
-pub use config_path::get_default_config_dir;
+pub use config_path::{get_default_cache_dir, get_default_config_dir, get_default_state_dir};
 pub use snippet::{Snippet, Trigger};
 
-use ansi_term::Color;
 use serde::{Deserialize, Serialize};
 use std::fs::{File, read_dir};
 use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use std::time::Instant;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -23,56 +24,112 @@ pub enum ConfigError {
     Yaml(#[from] serde_yaml::Error),
 }
 
+/// An error of a config file, which is skipped by [`Config::load_from_paths`].
+#[derive(Debug, Error)]
+#[error("failed to load config '{}': {source}", path.display())]
+pub struct LoadError {
+    pub path: PathBuf,
+    #[source]
+    pub source: ConfigError,
+}
+
+/// Precommand modifiers and wrappers after which the next word is in command position.
+static DEFAULT_PRECOMMANDS: &[&str] = &[
+    "sudo",
+    "doas",
+    "env",
+    "nohup",
+    "time",
+    "command",
+    "builtin",
+    "exec",
+    "noglob",
+    "nocorrect",
+    "nice",
+];
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Config {
-    #[serde(rename = "snippets", alias = "abbrevs")]
+    #[serde(rename = "snippets", alias = "abbrevs", default)]
     pub snippets: Vec<Snippet>,
+
+    pub precommands: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub stats: bool,
 }
 
 impl Config {
-    #[cfg(test)]
     pub fn load_from_str(s: &str) -> Result<Self, ConfigError> {
         let config = toml::from_str(s)?;
         Ok(config)
     }
 
     pub fn load_from_file(path: &Path) -> Result<Self, ConfigError> {
+        let start = Instant::now();
         let mut file = File::open(path)?;
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer)?;
 
-        let config = if path.extension() == Some("toml".as_ref()) {
+        let config: Config = if path.extension() == Some("toml".as_ref()) {
             toml::from_slice(&buffer)?
         } else {
             serde_yaml::from_slice(&buffer)?
         };
+        log::debug!(
+            "loaded '{}' ({} snippets) in {:?}",
+            path.display(),
+            config.snippets.len(),
+            start.elapsed()
+        );
         Ok(config)
     }
 
-    pub fn load_or_exit() -> Self {
-        let config_dir = get_default_config_dir().expect("could not determine config directory");
-        let config_paths = Self::config_file_paths(Path::new(&config_dir))
-            .expect("failed to read config directory");
+    /// Loads and merges the config files in order, skipping those that fail to load.
+    pub fn load_from_paths(paths: &[PathBuf]) -> (Self, Vec<LoadError>) {
         let mut config: Config = Default::default();
+        let mut errors = Vec::new();
 
-        for path in &config_paths {
+        for path in paths {
             match Self::load_from_file(path) {
-                Ok(c) => config.merge(c),
-                Err(err) => {
-                    let error_message =
-                        format!("failed to load config '{}': {}", path.display(), err);
-                    let error_style = Color::Red.normal();
-
-                    eprintln!("{}", error_style.paint(error_message));
+                Ok(mut c) => {
+                    for snippet in &mut c.snippets {
+                        snippet.file = Some(path.clone());
+                    }
+                    config.merge(c);
                 }
+                Err(source) => errors.push(LoadError {
+                    path: path.clone(),
+                    source,
+                }),
             };
         }
 
-        config
+        (config, errors)
+    }
+
+    /// Loads the config files in `config_dir`. See [`Config::load_from_paths`].
+    pub fn load_from_dir(config_dir: &Path) -> Result<(Self, Vec<LoadError>), ConfigError> {
+        let config_paths = Self::config_file_paths(config_dir)?;
+        Ok(Self::load_from_paths(&config_paths))
     }
 
     fn merge(&mut self, mut other: Self) {
         self.snippets.append(&mut other.snippets);
+        self.stats |= other.stats;
+
+        if let Some(mut precommands) = other.precommands {
+            self.precommands
+                .get_or_insert_with(Vec::new)
+                .append(&mut precommands);
+        }
+    }
+
+    pub fn precommands(&self) -> Vec<&str> {
+        match &self.precommands {
+            Some(precommands) => precommands.iter().map(String::as_str).collect(),
+            None => DEFAULT_PRECOMMANDS.to_vec(),
+        }
     }
 
     pub fn config_file_paths(config_dir: &Path) -> io::Result<Vec<PathBuf>> {
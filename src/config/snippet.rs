@@ -1,12 +1,24 @@
-use ansi_term::Color;
+use crate::config::template::{self, TemplateContext, TemplateError};
+use crate::expand::RecursionError;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
 
-#[derive(Debug, Error)]
+#[derive(Debug, Error, PartialEq)]
 pub enum ExpandError {
     #[error("invalid regex: {0}")]
     Regex(#[from] regex::Error),
+
+    #[error("invalid template: {0}")]
+    Template(#[from] TemplateError),
+
+    #[error(transparent)]
+    Recursion(#[from] RecursionError),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,31 +38,44 @@ impl Trigger {
             Trigger::Text(trigger) if trigger == last_arg => Ok(Some(vec![])),
             Trigger::Text(_) => Ok(None),
             Trigger::Regex(regex) => {
-                let pattern = Regex::new(regex)?;
-
-                let matches = pattern.captures(last_arg);
-                let matches = match matches {
-                    Some(matches) => matches,
-                    None => return Ok(None),
-                };
-
-                let captures = pattern
-                    .capture_names()
-                    .flatten()
-                    .filter_map(|name| {
-                        matches.name(name).map(|value| Capture {
-                            name: name.to_string(),
-                            value: value.as_str(),
-                        })
-                    })
-                    .collect();
-
-                Ok(Some(captures))
+                let pattern = compile_regex(regex)?;
+                Ok(named_captures(&pattern, last_arg))
             }
         }
     }
 }
 
+/// Compiles `pattern`, reusing the result for the same pattern, as snippets are matched many times by `expand-line`.
+fn compile_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    thread_local! {
+        static CACHE: RefCell<HashMap<String, Result<Regex, regex::Error>>> = RefCell::default();
+    }
+
+    CACHE.with_borrow_mut(|cache| {
+        cache
+            .entry(pattern.to_string())
+            .or_insert_with(|| Regex::new(pattern))
+            .clone()
+    })
+}
+
+fn named_captures<'a>(pattern: &Regex, haystack: &'a str) -> Option<Vec<Capture<'a>>> {
+    let matches = pattern.captures(haystack)?;
+
+    let captures = pattern
+        .capture_names()
+        .flatten()
+        .filter_map(|name| {
+            matches.name(name).map(|value| Capture {
+                name: name.to_string(),
+                value: value.as_str(),
+            })
+        })
+        .collect();
+
+    Some(captures)
+}
+
 #[derive(Debug, Eq, PartialEq)]
 pub struct Capture<'a> {
     pub name: String,
@@ -64,12 +89,85 @@ pub enum Action {
     ReplaceLast,
     #[serde(rename = "replace-all")]
     ReplaceAll,
+    #[serde(rename = "replace-command")]
+    ReplaceCommand,
+    #[serde(rename = "replace-line")]
+    ReplaceLine,
+    #[serde(rename = "prepend")]
+    Prepend,
+    #[serde(rename = "append")]
+    Append,
+}
+
+/// A key that expands abbreviations.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExpandKey {
+    Space,
+    Enter,
+    Pipe,
+    Semicolon,
+    Ampersand,
+}
+
+#[derive(Debug, Error)]
+#[error("invalid cache duration '{0}'")]
+pub struct InvalidCacheError(String);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Cache {
+    Session,
+    Ttl(Duration),
+}
+
+impl TryFrom<String> for Cache {
+    type Error = InvalidCacheError;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        if s == "session" {
+            return Ok(Cache::Session);
+        }
+
+        let unit = match s.char_indices().last() {
+            Some((_, 's')) => 1,
+            Some((_, 'm')) => 60,
+            Some((_, 'h')) => 60 * 60,
+            Some((_, 'd')) => 24 * 60 * 60,
+            _ => return Err(InvalidCacheError(s)),
+        };
+
+        match s[..s.len() - 1]
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(unit))
+        {
+            Some(secs) => Ok(Cache::Ttl(Duration::from_secs(secs))),
+            None => Err(InvalidCacheError(s)),
+        }
+    }
+}
+
+impl From<Cache> for String {
+    fn from(cache: Cache) -> Self {
+        match cache {
+            Cache::Session => "session".to_string(),
+            Cache::Ttl(ttl) => format!("{}s", ttl.as_secs()),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Snippet {
     pub name: Option<String>,
 
+    /// A longer explanation shown by `zabrze list` and in completion.
+    pub description: Option<String>,
+
+    /// Labels for grouping snippets, filtered by `zabrze list --tag`.
+    #[serde(default)]
+    pub tags: Vec<String>,
+
     #[serde(flatten)]
     pub trigger: Trigger,
 
@@ -87,56 +185,158 @@ pub struct Snippet {
     #[serde(rename = "if")]
     pub condition: Option<String>,
 
+    #[serde(rename = "command")]
+    pub command: Option<String>,
+
+    pub position: Option<usize>,
+
+    #[serde(default)]
+    pub after: Vec<String>,
+
+    #[serde(rename = "min-args")]
+    pub min_args: Option<usize>,
+
+    #[serde(rename = "max-args")]
+    pub max_args: Option<usize>,
+
     #[serde(default = "default_as_false")]
     pub global: bool,
 
     #[serde(default = "default_as_false")]
     pub evaluate: bool,
 
+    #[serde(default = "default_as_false")]
+    pub template: bool,
+
     #[serde(rename = "abort-on-error", default = "default_as_false")]
     pub abort_on_error: bool,
+
+    #[serde(rename = "quote-captures", default = "default_as_false")]
+    pub quote_captures: bool,
+
+    pub cache: Option<Cache>,
+
+    #[serde(default = "default_as_false")]
+    pub recursive: bool,
+
+    /// The keys that expand the snippet, or all keys if `None`.
+    #[serde(rename = "expand-on")]
+    pub expand_on: Option<Vec<ExpandKey>>,
+
+    /// Examples of expanding the snippet, run by `zabrze test`.
+    #[serde(default)]
+    pub tests: Vec<SnippetTest>,
+
+    /// The config file that defines the snippet.
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
+}
+
+/// An example of expanding a snippet, checked by `zabrze test`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnippetTest {
+    /// The text before the cursor.
+    pub lbuffer: String,
+
+    /// The text after the cursor.
+    #[serde(default)]
+    pub rbuffer: String,
+
+    /// The key typed to expand, checked against `expand-on`.
+    pub key: Option<ExpandKey>,
+
+    /// Whether the snippet is expected to expand. Defaults to `matched`.
+    pub expect: Option<Expect>,
+
+    #[serde(rename = "expect-lbuffer")]
+    pub expect_lbuffer: Option<String>,
+
+    #[serde(rename = "expect-rbuffer")]
+    pub expect_rbuffer: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Expect {
+    Matched,
+    Unmatched,
 }
 
 impl Snippet {
-    pub fn do_match<'a>(&'a self, command: &str, last_arg: &'a str) -> Option<Match<'a>> {
-        match self.do_match_impl(command, last_arg) {
-            Ok(m) => m,
-            Err(error) => {
-                let name = self.name.as_ref().unwrap_or(&self.snippet);
-                let error_message = format!("snippet '{}': {}", name, error);
-                let error_style = Color::Red.normal();
-
-                eprintln!("{}", error_style.paint(error_message));
-                None
-            }
+    /// Returns the name of the snippet, or the snippet itself if it is unnamed.
+    pub fn display_name(&self) -> &str {
+        self.name.as_ref().unwrap_or(&self.snippet)
+    }
+
+    /// Returns `true` if typing `key` expands the snippet. Any snippet expands on an unknown key (`None`).
+    pub fn expands_on(&self, key: Option<ExpandKey>) -> bool {
+        match (&self.expand_on, key) {
+            (Some(keys), Some(key)) => keys.contains(&key),
+            _ => true,
         }
     }
 
-    fn do_match_impl<'a>(
+    /// Matches the snippet against `argv`, the words from the command word to the word under the cursor.
+    ///
+    /// `line` is the command up to the word under the cursor and is matched by `context`.
+    /// `command` is the part of `line` from the command word, after precommand modifiers and assignments.
+    /// An empty `argv` matches nothing.
+    pub fn do_match<'a>(
         &'a self,
-        command: &str,
-        last_arg: &'a str,
+        line: &'a str,
+        command: &'a str,
+        argv: &[&'a str],
     ) -> Result<Option<Match<'a>>, ExpandError> {
-        if !(self.global || command == last_arg) {
+        let Some(&last_arg) = argv.last() else {
+            return Ok(None);
+        };
+
+        if !self.match_position(argv) {
             return Ok(None);
         }
 
-        let captures = match self.trigger.match_pattern(last_arg)? {
+        let trigger_captures = match self.trigger.match_pattern(last_arg)? {
             Some(captures) => captures,
             None => return Ok(None),
         };
 
-        if !self.match_context(command)? {
-            return Ok(None);
-        }
+        let context_captures = match self.match_context(line)? {
+            Some(captures) => captures,
+            None => return Ok(None),
+        };
 
-        let matched_snippet = self
-            .cursor
-            .as_ref()
-            .filter(|cursor| !cursor.is_empty())
-            .and_then(|cursor| self.snippet.split_once(cursor))
-            .map(|(left, right)| MatchedSnippet::WithPlaceholder { left, right })
-            .unwrap_or_else(|| MatchedSnippet::Simple(&self.snippet));
+        // Captures from `trigger-pattern` take precedence over those from `context` with the same name
+        let mut captures: Vec<_> = context_captures
+            .into_iter()
+            .filter(|c| trigger_captures.iter().all(|t| t.name != c.name))
+            .collect();
+        captures.extend(trigger_captures);
+
+        // The cursor is split off before rendering, skipping a marker inside `{{ ... }}` tags
+        let cursor = self.cursor.as_deref().filter(|cursor| !cursor.is_empty());
+        let cursor_index = match cursor {
+            Some(cursor) if self.template => template::find_outside_tags(&self.snippet, cursor)?,
+            Some(cursor) => self.snippet.find(cursor),
+            None => None,
+        };
+        let matched_snippet = match (cursor, cursor_index) {
+            (Some(cursor), Some(i)) => MatchedSnippet::WithPlaceholder {
+                left: Cow::from(&self.snippet[..i]),
+                right: Cow::from(&self.snippet[i + cursor.len()..]),
+            },
+            _ => MatchedSnippet::Simple(Cow::from(&self.snippet)),
+        };
+
+        let matched_snippet = if self.template {
+            let ctx = TemplateContext {
+                command,
+                trigger: last_arg,
+                captures: &captures,
+            };
+            matched_snippet.render(&ctx)?
+        } else {
+            matched_snippet
+        };
 
         Ok(Some(Match {
             snippet: self,
@@ -145,14 +345,47 @@ impl Snippet {
         }))
     }
 
-    fn match_context(&self, command: &str) -> Result<bool, ExpandError> {
+    fn match_position(&self, argv: &[&str]) -> bool {
+        let position = argv.len() - 1;
+        let has_argv_condition = self.command.is_some()
+            || !self.after.is_empty()
+            || self.min_args.is_some()
+            || self.max_args.is_some();
+
+        match self.position {
+            Some(p) if p != position => return false,
+            Some(_) => {}
+            // Snippets with argv conditions expand only in argument positions
+            None if has_argv_condition && position == 0 => return false,
+            None if !(self.global || has_argv_condition || position == 0) => return false,
+            None => {}
+        }
+
+        if let Some(command) = &self.command
+            && argv[0] != command
+        {
+            return false;
+        }
+
+        if !self.after.is_empty() {
+            let previous = position.checked_sub(1).map(|i| argv[i]);
+            if !previous.is_some_and(|p| self.after.iter().any(|a| a == p)) {
+                return false;
+            }
+        }
+
+        let args = position.saturating_sub(1);
+        self.min_args.is_none_or(|min| args >= min) && self.max_args.is_none_or(|max| args <= max)
+    }
+
+    fn match_context<'a>(&self, line: &'a str) -> Result<Option<Vec<Capture<'a>>>, ExpandError> {
         let context = match &self.context {
             Some(context) => context,
-            None => return Ok(true), // No context means always match
+            None => return Ok(Some(vec![])), // No context means always match
         };
 
-        let context_pattern = Regex::new(context)?;
-        Ok(context_pattern.is_match(command))
+        let context_pattern = compile_regex(context)?;
+        Ok(named_captures(&context_pattern, line))
     }
 }
 
@@ -164,17 +397,17 @@ pub struct Match<'a> {
 }
 
 impl<'a> Match<'a> {
-    pub fn left_snippet(&self) -> &'a str {
-        match self.matched_snippet {
-            MatchedSnippet::Simple(s) => s,
-            MatchedSnippet::WithPlaceholder { left, right: _ } => left,
+    pub fn left_snippet(&self) -> Cow<'a, str> {
+        match &self.matched_snippet {
+            MatchedSnippet::Simple(s) => s.clone(),
+            MatchedSnippet::WithPlaceholder { left, right: _ } => left.clone(),
         }
     }
 
-    pub fn right_snippet(&self) -> &'a str {
-        match self.matched_snippet {
-            MatchedSnippet::Simple(_) => "",
-            MatchedSnippet::WithPlaceholder { left: _, right } => right,
+    pub fn right_snippet(&self) -> Cow<'a, str> {
+        match &self.matched_snippet {
+            MatchedSnippet::Simple(_) => Cow::from(""),
+            MatchedSnippet::WithPlaceholder { left: _, right } => right.clone(),
         }
     }
 
@@ -185,6 +418,18 @@ impl<'a> Match<'a> {
         )
     }
 
+    pub fn snippet(&self) -> &'a Snippet {
+        self.snippet
+    }
+
+    pub fn name(&self) -> &'a str {
+        self.snippet.display_name()
+    }
+
+    pub fn file(&self) -> Option<&'a Path> {
+        self.snippet.file.as_deref()
+    }
+
     pub fn action(&self) -> &'a Action {
         &self.snippet.action
     }
@@ -200,15 +445,52 @@ impl<'a> Match<'a> {
     pub fn abort_on_error(&self) -> bool {
         self.snippet.abort_on_error
     }
+
+    pub fn quote_captures(&self) -> bool {
+        self.snippet.quote_captures
+    }
+
+    pub fn cache(&self) -> Option<Cache> {
+        self.snippet.cache.filter(|_| self.snippet.evaluate)
+    }
+
+    pub fn recursive(&self) -> bool {
+        self.snippet.recursive
+    }
 }
 
 #[derive(Debug)]
 pub enum MatchedSnippet<'a> {
-    Simple(&'a str),
-    WithPlaceholder { left: &'a str, right: &'a str },
+    Simple(Cow<'a, str>),
+    WithPlaceholder {
+        left: Cow<'a, str>,
+        right: Cow<'a, str>,
+    },
+}
+
+impl<'a> MatchedSnippet<'a> {
+    fn render(self, ctx: &TemplateContext) -> Result<Self, TemplateError> {
+        let render = |s: Cow<'a, str>| -> Result<Cow<'a, str>, TemplateError> {
+            match s {
+                Cow::Borrowed(s) => template::render(s, ctx),
+                Cow::Owned(s) => Ok(Cow::from(template::render(&s, ctx)?.into_owned())),
+            }
+        };
+
+        match self {
+            MatchedSnippet::Simple(s) => Ok(MatchedSnippet::Simple(render(s)?)),
+            MatchedSnippet::WithPlaceholder { left, right } => {
+                Ok(MatchedSnippet::WithPlaceholder {
+                    left: render(left)?,
+                    right: render(right)?,
+                })
+            }
+        }
+    }
 }
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
     use super::*;
 
@@ -230,7 +512,6 @@ mod tests {
             testname: &'static str,
             snippet: Snippet,
             command: &'static str,
-            last_arg: &'static str,
             expected: Option<TestMatch>,
         }
 
@@ -239,18 +520,31 @@ mod tests {
                 testname: "should match non-global if first arg",
                 snippet: Snippet {
                     name: None,
+                    description: None,
+                    tags: vec![],
                     trigger: Trigger::Text("test".to_string()),
                     snippet: "TEST".to_string(),
                     cursor: Some("{}".to_string()),
                     action: Action::ReplaceLast,
                     context: None,
                     condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
                     global: false,
                     evaluate: false,
+                    template: false,
                     abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
                 },
                 command: "test",
-                last_arg: "test",
                 expected: Some(TestMatch {
                     left: "TEST",
                     right: "",
@@ -258,40 +552,97 @@ mod tests {
                     has_placeholder: false,
                 }),
             },
+            Scenario {
+                testname: "should not match empty argv",
+                snippet: Snippet {
+                    name: None,
+                    description: None,
+                    tags: vec![],
+                    trigger: Trigger::Text("test".to_string()),
+                    snippet: "TEST".to_string(),
+                    cursor: Some("{}".to_string()),
+                    action: Action::ReplaceLast,
+                    context: None,
+                    condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
+                    global: true,
+                    evaluate: false,
+                    template: false,
+                    abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
+                },
+                command: "",
+                expected: None,
+            },
             Scenario {
                 testname: "should not match non-global if second arg",
                 snippet: Snippet {
                     name: None,
+                    description: None,
+                    tags: vec![],
                     trigger: Trigger::Text("test".to_string()),
                     snippet: "TEST".to_string(),
                     cursor: Some("{}".to_string()),
                     action: Action::ReplaceLast,
                     context: None,
                     condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
                     global: false,
                     evaluate: false,
+                    template: false,
                     abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
                 },
                 command: "echo test",
-                last_arg: "test",
                 expected: None,
             },
             Scenario {
                 testname: "should match global",
                 snippet: Snippet {
                     name: None,
+                    description: None,
+                    tags: vec![],
                     trigger: Trigger::Text("test".to_string()),
                     snippet: "TEST".to_string(),
                     cursor: Some("{}".to_string()),
                     action: Action::ReplaceLast,
                     context: None,
                     condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
                     global: true,
                     evaluate: false,
+                    template: false,
                     abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
                 },
                 command: "echo test",
-                last_arg: "test",
                 expected: Some(TestMatch {
                     left: "TEST",
                     right: "",
@@ -303,18 +654,31 @@ mod tests {
                 testname: "should match global with context",
                 snippet: Snippet {
                     name: None,
+                    description: None,
+                    tags: vec![],
                     trigger: Trigger::Text("test".to_string()),
                     snippet: "TEST".to_string(),
                     cursor: Some("{}".to_string()),
                     action: Action::ReplaceLast,
                     context: Some("^echo ".to_string()),
                     condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
                     global: true,
                     evaluate: false,
+                    template: false,
                     abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
                 },
                 command: "echo test",
-                last_arg: "test",
                 expected: Some(TestMatch {
                     left: "TEST",
                     right: "",
@@ -326,54 +690,93 @@ mod tests {
                 testname: "should not match global with context",
                 snippet: Snippet {
                     name: None,
+                    description: None,
+                    tags: vec![],
                     trigger: Trigger::Text("test".to_string()),
                     snippet: "TEST".to_string(),
                     cursor: Some("{}".to_string()),
                     action: Action::ReplaceLast,
                     context: Some("^printf ".to_string()),
                     condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
                     global: true,
                     evaluate: false,
+                    template: false,
                     abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
                 },
                 command: "echo test",
-                last_arg: "test",
                 expected: None,
             },
             Scenario {
                 testname: "should not match if context is invalid",
                 snippet: Snippet {
                     name: None,
+                    description: None,
+                    tags: vec![],
                     trigger: Trigger::Text("test".to_string()),
                     snippet: "TEST".to_string(),
                     cursor: Some("{}".to_string()),
                     action: Action::ReplaceLast,
                     context: Some("(echo".to_string()),
                     condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
                     global: true,
                     evaluate: false,
+                    template: false,
                     abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
                 },
                 command: "echo test",
-                last_arg: "test",
                 expected: None,
             },
             Scenario {
                 testname: "should match with placeholder",
                 snippet: Snippet {
                     name: None,
+                    description: None,
+                    tags: vec![],
                     trigger: Trigger::Text("test".to_string()),
                     snippet: "TE{}ST".to_string(),
                     cursor: Some("{}".to_string()),
                     action: Action::ReplaceLast,
                     context: None,
                     condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
                     global: false,
                     evaluate: false,
+                    template: false,
                     abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
                 },
                 command: "test",
-                last_arg: "test",
                 expected: Some(TestMatch {
                     left: "TE",
                     right: "ST",
@@ -385,18 +788,31 @@ mod tests {
                 testname: "should not match if cursor is none",
                 snippet: Snippet {
                     name: None,
+                    description: None,
+                    tags: vec![],
                     trigger: Trigger::Text("test".to_string()),
                     snippet: "TE{}ST".to_string(),
                     cursor: None,
                     action: Action::ReplaceLast,
                     context: None,
                     condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
                     global: false,
                     evaluate: false,
+                    template: false,
                     abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
                 },
                 command: "test",
-                last_arg: "test",
                 expected: Some(TestMatch {
                     left: "TE{}ST",
                     right: "",
@@ -408,18 +824,31 @@ mod tests {
                 testname: "should match with custom placeholder",
                 snippet: Snippet {
                     name: None,
+                    description: None,
+                    tags: vec![],
                     trigger: Trigger::Text("test".to_string()),
                     snippet: "TE👇ST".to_string(),
                     cursor: Some("👇".to_string()),
                     action: Action::ReplaceLast,
                     context: None,
                     condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
                     global: false,
                     evaluate: false,
+                    template: false,
                     abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
                 },
                 command: "test",
-                last_arg: "test",
                 expected: Some(TestMatch {
                     left: "TE",
                     right: "ST",
@@ -431,18 +860,31 @@ mod tests {
                 testname: "should match trigger-pattern",
                 snippet: Snippet {
                     name: None,
+                    description: None,
+                    tags: vec![],
                     trigger: Trigger::Regex(r"\.py$".to_string()),
                     snippet: "python3".to_string(),
                     cursor: Some("{}".to_string()),
                     action: Action::ReplaceLast,
                     context: None,
                     condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
                     global: false,
                     evaluate: false,
+                    template: false,
                     abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
                 },
                 command: "test.py",
-                last_arg: "test.py",
                 expected: Some(TestMatch {
                     left: "python3",
                     right: "",
@@ -454,18 +896,31 @@ mod tests {
                 testname: "should capture named groups (?P<...>)",
                 snippet: Snippet {
                     name: None,
+                    description: None,
+                    tags: vec![],
                     trigger: Trigger::Regex(r"^\.(?P<digits>\d+)$".to_string()),
                     snippet: r".\$$n".to_string(),
                     cursor: Some("{}".to_string()),
                     action: Action::ReplaceLast,
                     context: None,
                     condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
                     global: false,
                     evaluate: false,
+                    template: false,
                     abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
                 },
                 command: ".3",
-                last_arg: ".3",
                 expected: Some(TestMatch {
                     left: r".\$$n",
                     right: "",
@@ -480,18 +935,31 @@ mod tests {
                 testname: "should capture named groups (?<...>)",
                 snippet: Snippet {
                     name: None,
+                    description: None,
+                    tags: vec![],
                     trigger: Trigger::Regex(r"^\.(?<digits>\d+)$".to_string()),
                     snippet: r".\$$n".to_string(),
                     cursor: Some("{}".to_string()),
                     action: Action::ReplaceLast,
                     context: None,
                     condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
                     global: false,
                     evaluate: false,
+                    template: false,
                     abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
                 },
                 command: ".42",
-                last_arg: ".42",
                 expected: Some(TestMatch {
                     left: r".\$$n",
                     right: "",
@@ -502,10 +970,363 @@ mod tests {
                     has_placeholder: false,
                 }),
             },
+            Scenario {
+                testname: "should capture named groups in context",
+                snippet: Snippet {
+                    name: None,
+                    description: None,
+                    tags: vec![],
+                    trigger: Trigger::Text("pods".to_string()),
+                    snippet: "get pods -n $ns".to_string(),
+                    cursor: Some("{}".to_string()),
+                    action: Action::ReplaceLast,
+                    context: Some(r"^kubectl -n (?<ns>\S+)".to_string()),
+                    condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
+                    global: true,
+                    evaluate: true,
+                    template: false,
+                    abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
+                },
+                command: "kubectl -n kube-system pods",
+                expected: Some(TestMatch {
+                    left: "get pods -n $ns",
+                    right: "",
+                    captures: &[TestCapture {
+                        name: "ns",
+                        value: "kube-system",
+                    }],
+                    has_placeholder: false,
+                }),
+            },
+            Scenario {
+                testname: "should prefer trigger-pattern captures to context captures",
+                snippet: Snippet {
+                    name: None,
+                    description: None,
+                    tags: vec![],
+                    trigger: Trigger::Regex(r"^(?<x>\d+)$".to_string()),
+                    snippet: "$x $y".to_string(),
+                    cursor: Some("{}".to_string()),
+                    action: Action::ReplaceLast,
+                    context: Some(r"^(?<x>\w+) (?<y>\w+)".to_string()),
+                    condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
+                    global: true,
+                    evaluate: true,
+                    template: false,
+                    abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
+                },
+                command: "echo hello 42",
+                expected: Some(TestMatch {
+                    left: "$x $y",
+                    right: "",
+                    captures: &[
+                        TestCapture {
+                            name: "y",
+                            value: "hello",
+                        },
+                        TestCapture {
+                            name: "x",
+                            value: "42",
+                        },
+                    ],
+                    has_placeholder: false,
+                }),
+            },
+            Scenario {
+                testname: "should match command and position",
+                snippet: Snippet {
+                    name: None,
+                    description: None,
+                    tags: vec![],
+                    trigger: Trigger::Text("c".to_string()),
+                    snippet: "commit".to_string(),
+                    cursor: Some("{}".to_string()),
+                    action: Action::ReplaceLast,
+                    context: None,
+                    condition: None,
+                    command: Some("git".to_string()),
+                    position: Some(1),
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
+                    global: false,
+                    evaluate: false,
+                    template: false,
+                    abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
+                },
+                command: "git c",
+                expected: Some(TestMatch {
+                    left: "commit",
+                    right: "",
+                    captures: &[],
+                    has_placeholder: false,
+                }),
+            },
+            Scenario {
+                testname: "should not match command in other position",
+                snippet: Snippet {
+                    name: None,
+                    description: None,
+                    tags: vec![],
+                    trigger: Trigger::Text("c".to_string()),
+                    snippet: "commit".to_string(),
+                    cursor: Some("{}".to_string()),
+                    action: Action::ReplaceLast,
+                    context: None,
+                    condition: None,
+                    command: Some("git".to_string()),
+                    position: Some(1),
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
+                    global: false,
+                    evaluate: false,
+                    template: false,
+                    abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
+                },
+                command: "git log c",
+                expected: None,
+            },
+            Scenario {
+                testname: "should not match other command",
+                snippet: Snippet {
+                    name: None,
+                    description: None,
+                    tags: vec![],
+                    trigger: Trigger::Text("c".to_string()),
+                    snippet: "commit".to_string(),
+                    cursor: Some("{}".to_string()),
+                    action: Action::ReplaceLast,
+                    context: None,
+                    condition: None,
+                    command: Some("git".to_string()),
+                    position: Some(1),
+                    after: vec![],
+                    min_args: None,
+                    max_args: None,
+                    global: false,
+                    evaluate: false,
+                    template: false,
+                    abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
+                },
+                command: "tig c",
+                expected: None,
+            },
+            Scenario {
+                testname: "should match after option",
+                snippet: Snippet {
+                    name: None,
+                    description: None,
+                    tags: vec![],
+                    trigger: Trigger::Text("ks".to_string()),
+                    snippet: "kube-system".to_string(),
+                    cursor: Some("{}".to_string()),
+                    action: Action::ReplaceLast,
+                    context: None,
+                    condition: None,
+                    command: Some("kubectl".to_string()),
+                    position: None,
+                    after: vec!["-n".to_string(), "--namespace".to_string()],
+                    min_args: None,
+                    max_args: None,
+                    global: false,
+                    evaluate: false,
+                    template: false,
+                    abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
+                },
+                command: "kubectl get pods -n ks",
+                expected: Some(TestMatch {
+                    left: "kube-system",
+                    right: "",
+                    captures: &[],
+                    has_placeholder: false,
+                }),
+            },
+            Scenario {
+                testname: "should not match without preceding option",
+                snippet: Snippet {
+                    name: None,
+                    description: None,
+                    tags: vec![],
+                    trigger: Trigger::Text("ks".to_string()),
+                    snippet: "kube-system".to_string(),
+                    cursor: Some("{}".to_string()),
+                    action: Action::ReplaceLast,
+                    context: None,
+                    condition: None,
+                    command: Some("kubectl".to_string()),
+                    position: None,
+                    after: vec!["-n".to_string(), "--namespace".to_string()],
+                    min_args: None,
+                    max_args: None,
+                    global: false,
+                    evaluate: false,
+                    template: false,
+                    abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
+                },
+                command: "kubectl ks",
+                expected: None,
+            },
+            Scenario {
+                testname: "should not match argv condition in command position",
+                snippet: Snippet {
+                    name: None,
+                    description: None,
+                    tags: vec![],
+                    trigger: Trigger::Text("ks".to_string()),
+                    snippet: "kube-system".to_string(),
+                    cursor: Some("{}".to_string()),
+                    action: Action::ReplaceLast,
+                    context: None,
+                    condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: Some(0),
+                    max_args: None,
+                    global: false,
+                    evaluate: false,
+                    template: false,
+                    abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
+                },
+                command: "ks",
+                expected: None,
+            },
+            Scenario {
+                testname: "should match min-args",
+                snippet: Snippet {
+                    name: None,
+                    description: None,
+                    tags: vec![],
+                    trigger: Trigger::Text("x".to_string()),
+                    snippet: "X".to_string(),
+                    cursor: Some("{}".to_string()),
+                    action: Action::ReplaceLast,
+                    context: None,
+                    condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: Some(1),
+                    max_args: None,
+                    global: false,
+                    evaluate: false,
+                    template: false,
+                    abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
+                },
+                command: "echo a x",
+                expected: Some(TestMatch {
+                    left: "X",
+                    right: "",
+                    captures: &[],
+                    has_placeholder: false,
+                }),
+            },
+            Scenario {
+                testname: "should not match min-args",
+                snippet: Snippet {
+                    name: None,
+                    description: None,
+                    tags: vec![],
+                    trigger: Trigger::Text("x".to_string()),
+                    snippet: "X".to_string(),
+                    cursor: Some("{}".to_string()),
+                    action: Action::ReplaceLast,
+                    context: None,
+                    condition: None,
+                    command: None,
+                    position: None,
+                    after: vec![],
+                    min_args: Some(1),
+                    max_args: None,
+                    global: false,
+                    evaluate: false,
+                    template: false,
+                    abort_on_error: false,
+                    quote_captures: false,
+                    cache: None,
+                    recursive: false,
+                    expand_on: None,
+                    tests: vec![],
+                    file: None,
+                },
+                command: "echo x",
+                expected: None,
+            },
         ];
 
         for s in scenarios {
-            let actual = s.snippet.do_match(s.command, s.last_arg);
+            let argv: Vec<_> = s.command.split_whitespace().collect();
+            // An invalid snippet is an error, which does not match either
+            let actual = s
+                .snippet
+                .do_match(s.command, s.command, &argv)
+                .ok()
+                .flatten();
 
             match (actual, &s.expected) {
                 (Some(actual), Some(expected)) => {
@@ -543,6 +1364,22 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_parse_cache() {
+        let parse = |s: &str| Cache::try_from(s.to_string()).ok();
+
+        assert_eq!(parse("session"), Some(Cache::Session));
+        assert_eq!(parse("30s"), Some(Cache::Ttl(Duration::from_secs(30))));
+        assert_eq!(parse("5m"), Some(Cache::Ttl(Duration::from_secs(300))));
+        assert_eq!(parse("2h"), Some(Cache::Ttl(Duration::from_secs(7200))));
+        assert_eq!(parse("1d"), Some(Cache::Ttl(Duration::from_secs(86400))));
+        assert_eq!(parse("30"), None);
+        assert_eq!(parse("s"), None);
+        assert_eq!(parse("-1s"), None);
+        assert_eq!(parse("1秒"), None);
+        assert_eq!(parse("999999999999999999d"), None);
+    }
 }
 
 fn default_cursor() -> Option<String> {
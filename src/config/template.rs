@@ -0,0 +1,291 @@
+use crate::config::snippet::Capture;
+use shell_escape::escape;
+use std::borrow::Cow;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Error, Eq, PartialEq)]
+pub enum TemplateError {
+    #[error("unclosed tag '{{{{'")]
+    UnclosedTag,
+
+    #[error("unclosed string literal in '{0}'")]
+    UnclosedString(String),
+
+    #[error("unknown variable '{0}'")]
+    UnknownVariable(String),
+
+    #[error("unknown filter '{0}'")]
+    UnknownFilter(String),
+
+    #[error("invalid filter arguments in '{0}'")]
+    InvalidArguments(String),
+}
+
+#[derive(Debug)]
+pub struct TemplateContext<'a> {
+    pub command: &'a str,
+    pub trigger: &'a str,
+    pub captures: &'a [Capture<'a>],
+}
+
+impl TemplateContext<'_> {
+    fn lookup(&self, path: &str) -> Result<Option<String>, TemplateError> {
+        match path.split_once('.') {
+            None if path == "command" => Ok(Some(self.command.to_string())),
+            None if path == "trigger" => Ok(Some(self.trigger.to_string())),
+            Some(("captures", name)) => Ok(self
+                .captures
+                .iter()
+                .find(|c| c.name == name)
+                .map(|c| c.value.to_string())),
+            _ => Err(TemplateError::UnknownVariable(path.to_string())),
+        }
+    }
+}
+
+/// Renders `{{ expr | filter | ... }}` tags in `template`.
+pub fn render<'t>(template: &'t str, ctx: &TemplateContext) -> Result<Cow<'t, str>, TemplateError> {
+    if !template.contains("{{") {
+        return Ok(Cow::from(template));
+    }
+
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+
+        let tag = &rest[start + 2..];
+        let end = find_tag_end(tag)?;
+        output.push_str(&eval_expr(&tag[..end], ctx)?);
+
+        rest = &tag[end + 2..];
+    }
+    output.push_str(rest);
+
+    Ok(Cow::from(output))
+}
+
+/// Returns the index of the first `pattern` in `template` that is not inside a `{{ ... }}` tag.
+pub fn find_outside_tags(template: &str, pattern: &str) -> Result<Option<usize>, TemplateError> {
+    let mut offset = 0;
+    loop {
+        let rest = &template[offset..];
+        match (rest.find(pattern), rest.find("{{")) {
+            (Some(i), Some(start)) if i < start => return Ok(Some(offset + i)),
+            (Some(i), None) => return Ok(Some(offset + i)),
+            (_, Some(start)) => {
+                let tag = &rest[start + 2..];
+                offset += start + 2 + find_tag_end(tag)? + 2;
+            }
+            (None, None) => return Ok(None),
+        }
+    }
+}
+
+fn find_tag_end(tag: &str) -> Result<usize, TemplateError> {
+    let mut quote = None;
+    for (i, c) in tag.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if tag[i..].starts_with("}}") => return Ok(i),
+            None => {}
+        }
+    }
+    Err(TemplateError::UnclosedTag)
+}
+
+fn eval_expr(expr: &str, ctx: &TemplateContext) -> Result<String, TemplateError> {
+    let mut terms = split_pipes(expr)?.into_iter();
+
+    let head = terms.next().unwrap_or_default();
+    let mut value = match parse_string_literal(head, expr)? {
+        Some(literal) => Some(literal),
+        None => ctx.lookup(head)?,
+    };
+
+    for filter in terms {
+        value = apply_filter(filter, value, expr)?;
+    }
+
+    Ok(value.unwrap_or_default())
+}
+
+fn split_pipes(expr: &str) -> Result<Vec<&str>, TemplateError> {
+    let mut terms = Vec::new();
+    let mut quote = None;
+    let mut begin = 0;
+    for (i, c) in expr.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '|' => {
+                terms.push(expr[begin..i].trim());
+                begin = i + 1;
+            }
+            None => {}
+        }
+    }
+    if quote.is_some() {
+        return Err(TemplateError::UnclosedString(expr.trim().to_string()));
+    }
+    terms.push(expr[begin..].trim());
+    Ok(terms)
+}
+
+fn parse_string_literal(term: &str, expr: &str) -> Result<Option<String>, TemplateError> {
+    let quote = match term.chars().next() {
+        Some(q @ ('"' | '\'')) => q,
+        _ => return Ok(None),
+    };
+
+    match term[1..].strip_suffix(quote) {
+        Some(literal) if !literal.contains(quote) => Ok(Some(literal.to_string())),
+        _ => Err(TemplateError::UnclosedString(expr.trim().to_string())),
+    }
+}
+
+fn apply_filter(
+    filter: &str,
+    value: Option<String>,
+    expr: &str,
+) -> Result<Option<String>, TemplateError> {
+    let (name, argument) = match filter.split_once('(') {
+        Some((name, args)) => {
+            let args = args
+                .strip_suffix(')')
+                .ok_or_else(|| TemplateError::InvalidArguments(expr.trim().to_string()))?;
+            let argument = parse_string_literal(args.trim(), expr)?
+                .ok_or_else(|| TemplateError::InvalidArguments(expr.trim().to_string()))?;
+            (name.trim(), Some(argument))
+        }
+        None => (filter, None),
+    };
+
+    match (name, argument) {
+        ("upper", None) => Ok(value.map(|v| v.to_uppercase())),
+        ("lower", None) => Ok(value.map(|v| v.to_lowercase())),
+        ("quote", None) => Ok(Some(
+            escape(Cow::from(value.unwrap_or_default())).into_owned(),
+        )),
+        ("default", Some(default)) => Ok(value.filter(|v| !v.is_empty()).or(Some(default))),
+        ("upper" | "lower" | "quote" | "default", _) => {
+            Err(TemplateError::InvalidArguments(expr.trim().to_string()))
+        }
+        _ => Err(TemplateError::UnknownFilter(name.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let captures = [Capture {
+            name: "n".to_string(),
+            value: "2",
+        }];
+        let ctx = TemplateContext {
+            command: "cat a.txt | .2",
+            trigger: ".2",
+            captures: &captures,
+        };
+
+        struct Scenario {
+            testname: &'static str,
+            template: &'static str,
+            expected: Result<&'static str, TemplateError>,
+        }
+
+        let scenarios = &[
+            Scenario {
+                testname: "plain text",
+                template: "awk '{print $1}'",
+                expected: Ok("awk '{print $1}'"),
+            },
+            Scenario {
+                testname: "variables",
+                template: "{{ command }}/{{trigger}}/{{ captures.n }}",
+                expected: Ok("cat a.txt | .2/.2/2"),
+            },
+            Scenario {
+                testname: "no shell expansion",
+                template: "awk '{print ${{ captures.n }}}'",
+                expected: Ok("awk '{print $2}'"),
+            },
+            Scenario {
+                testname: "upper and lower",
+                template: "{{ 'Ab' | upper }}{{ 'Ab' | lower }}",
+                expected: Ok("ABab"),
+            },
+            Scenario {
+                testname: "quote",
+                template: "cd {{ 'a b$c' | quote }}",
+                expected: Ok("cd 'a b$c'"),
+            },
+            Scenario {
+                testname: "default",
+                template: "{{ captures.missing | default('x|y') }} {{ trigger | default(\"z\") }}",
+                expected: Ok("x|y .2"),
+            },
+            Scenario {
+                testname: "missing capture",
+                template: "[{{ captures.missing }}]",
+                expected: Ok("[]"),
+            },
+            Scenario {
+                testname: "literal braces",
+                template: "{{ '{{' }}",
+                expected: Ok("{{"),
+            },
+            Scenario {
+                testname: "unclosed tag",
+                template: "{{ trigger",
+                expected: Err(TemplateError::UnclosedTag),
+            },
+            Scenario {
+                testname: "unknown variable",
+                template: "{{ foo }}",
+                expected: Err(TemplateError::UnknownVariable("foo".to_string())),
+            },
+            Scenario {
+                testname: "unknown filter",
+                template: "{{ trigger | foo }}",
+                expected: Err(TemplateError::UnknownFilter("foo".to_string())),
+            },
+            Scenario {
+                testname: "missing default argument",
+                template: "{{ trigger | default }}",
+                expected: Err(TemplateError::InvalidArguments(
+                    "trigger | default".to_string(),
+                )),
+            },
+        ];
+
+        for s in scenarios {
+            let actual = render(s.template, &ctx);
+            let actual = actual.as_ref().map(|r| r.as_ref()).map_err(Clone::clone);
+            assert_eq!(actual, s.expected.clone(), "{}", s.testname);
+        }
+    }
+
+    #[test]
+    fn test_find_outside_tags() {
+        let scenarios = [
+            ("git commit -m '{}'", Ok(Some(15))),
+            ("{{ trigger }} {}", Ok(Some(14))),
+            ("{{ captures.x | default('{}') }}", Ok(None)),
+            ("{{ captures.x | default('{}') }}{}", Ok(Some(32))),
+            ("no cursor", Ok(None)),
+            ("{{ trigger {}", Err(TemplateError::UnclosedTag)),
+        ];
+
+        for (template, expected) in scenarios {
+            assert_eq!(find_outside_tags(template, "{}"), expected, "{template}");
+        }
+    }
+}
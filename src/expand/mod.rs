@@ -1,159 +1,267 @@
+mod recursion;
+
+pub use recursion::{RecursionError, expand_line, expand_snippet};
+
 use crate::config::Config;
-use crate::config::snippet::{Action, Match, Snippet};
-use crate::opt::ExpandArgs;
-use shell_escape::escape;
+use crate::config::snippet::{Action, Cache, ExpandError, ExpandKey, Match, Snippet};
+use crate::parser;
+use serde::{Serialize, Serializer};
 use std::borrow::Cow;
+use std::ops::Range;
+use std::path::Path;
+use std::time::Instant;
+use thiserror::Error;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, PartialEq, Serialize)]
 pub struct ExpandResult<'a> {
     pub command: &'a str,
     pub last_arg: &'a str,
     pub expansions: Vec<Expansion<'a>>,
+    /// Errors of the snippets skipped while matching.
+    #[serde(skip)]
+    pub errors: Vec<SnippetError<'a>>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Serialize)]
 pub struct ExpansionVariable<'a> {
     pub name: String,
     pub value: &'a str,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Serialize)]
 pub struct Expansion<'a> {
-    pub replacing_index: usize,
-    pub left_snippet: &'a str,
-    pub right_snippet: &'a str,
+    /// The range of `$BUFFER` to be replaced with the snippet.
+    pub replacing_range: Range<usize>,
+    pub left_snippet: Cow<'a, str>,
+    pub right_snippet: Cow<'a, str>,
     pub condition: Option<&'a str>,
     pub variables: Vec<ExpansionVariable<'a>>,
     pub evaluate: bool,
     pub has_placeholder: bool,
     pub abort_on_error: bool,
+    pub quote_captures: bool,
+    pub cache: Option<Cache>,
+    pub name: &'a str,
+    /// The index of the snippet in [`Config::snippets`].
+    #[serde(skip)]
+    pub index: usize,
+    #[serde(serialize_with = "serialize_path_lossy")]
+    pub file: Option<&'a Path>,
 }
 
-pub fn run(args: &ExpandArgs) {
-    let config = Config::load_or_exit();
-
-    let lbuffer = &args.lbuffer;
-    let rbuffer = &args.rbuffer;
+/// Serializes a path that may not be valid UTF-8, replacing invalid sequences.
+fn serialize_path_lossy<S: Serializer>(
+    path: &Option<&Path>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    path.map(Path::to_string_lossy).serialize(serializer)
+}
 
-    let result = expand(&config, lbuffer);
-    if result.expansions.is_empty() {
-        return;
+impl Expansion<'_> {
+    /// Returns `($LBUFFER, $RBUFFER)` after the expansion at `cursor`, a byte index of `buffer`.
+    ///
+    /// The snippet is inserted as is, without evaluation.
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`Expansion::split_buffer`].
+    pub fn apply(&self, buffer: &str, cursor: usize) -> (String, String) {
+        let (prefix, lbuffer_tail, rbuffer) = self.split_buffer(buffer, cursor);
+        (
+            format!("{prefix}{}{lbuffer_tail}", self.left_snippet),
+            format!("{}{rbuffer}", self.right_snippet),
+        )
     }
 
-    let command = escape(Cow::from(result.command));
-    let trigger = escape(Cow::from(result.last_arg));
-
-    print!(r#"local command={command} trigger={trigger} abbr={trigger};"#); // `abbr` is deprecated, remain for backward compatibility
-
-    let mut has_if = false;
-    for expansion in &result.expansions {
-        let prefix = escape(Cow::from(&lbuffer[..expansion.replacing_index]));
-        let left_snippet = escape(Cow::from(expansion.left_snippet));
-        let right_snippet = escape(Cow::from(expansion.right_snippet));
-        let condition = expansion.condition.map(|c| escape(Cow::from(c)));
-
-        let rbuffer = escape(Cow::from(rbuffer));
-        let eval_flag = if expansion.evaluate { "(e)" } else { "" };
-        let has_placeholder = if expansion.has_placeholder { "1" } else { "" };
-
-        if let Some(condition) = &condition {
-            if !has_if {
-                print!(r#"if eval {condition};then "#);
-            } else {
-                print!(r#"elif eval {condition};then "#);
-            }
-            has_if = true;
-        } else if has_if {
-            print!(r"else ");
-        }
-
-        for var in expansion.variables.iter() {
-            let name = escape(Cow::from(&var.name));
-            let value = escape(Cow::from(var.value));
-            print!(r#"local {name}={value};"#);
-        }
-
-        print!(r"local left_snippet={left_snippet} right_snippet={right_snippet};");
-        if expansion.abort_on_error {
-            print!(r#"if left_snippet="${{{eval_flag}left_snippet}}" && "#);
-            print!(r#"right_snippet="${{{eval_flag}right_snippet}}";then "#);
-            print!(r#"LBUFFER={prefix}"${{left_snippet}}";"#);
-            print!(r#"RBUFFER="${{right_snippet}}"{rbuffer};"#);
-            print!(r"__zabrze_has_placeholder={has_placeholder};");
-            print!(r#"fi"#);
+    /// Splits `buffer` around the replaced range into `(prefix, lbuffer_tail, rbuffer)`.
+    ///
+    /// The new buffer is `{prefix}{left_snippet}{lbuffer_tail}|{right_snippet}{rbuffer}`, where `|` is the cursor.
+    /// `cursor` is clamped as in [`expand`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is not the one that the expansion was made for, and `replacing_range` is out of it.
+    pub fn split_buffer<'b>(&self, buffer: &'b str, cursor: usize) -> (&'b str, &'b str, &'b str) {
+        let cursor = buffer.floor_char_boundary(cursor);
+        // Text between the replaced range and the cursor stays before the cursor, unless the cursor moves to the placeholder
+        let range = &self.replacing_range;
+        let prefix = &buffer[..range.start];
+        if self.has_placeholder || cursor <= range.end {
+            (prefix, "", &buffer[range.end..])
         } else {
-            print!(r#"LBUFFER={prefix}"${{{eval_flag}left_snippet}}";"#);
-            print!(r#"RBUFFER="${{{eval_flag}right_snippet}}"{rbuffer};"#);
-            print!(r"__zabrze_has_placeholder={has_placeholder};");
-        }
-
-        if condition.is_none() {
-            break;
+            (prefix, &buffer[range.end..cursor], &buffer[cursor..])
         }
     }
+}
+
+/// An error of a snippet that could not be matched or expanded.
+#[derive(Debug, Error, PartialEq)]
+#[error("snippet '{name}': {error}")]
+pub struct SnippetError<'a> {
+    pub name: &'a str,
+    /// The index of the snippet in [`Config::snippets`].
+    pub index: usize,
+    #[source]
+    pub error: ExpandError,
+}
 
-    if has_if {
-        print!(r"fi");
+/// Expands the word at `cursor`, a byte index of `buffer`, as if `key` were typed.
+///
+/// A cursor beyond the end of `buffer` is clamped to it, and one inside a character is moved to its start.
+/// Snippets with an error are skipped and reported in [`ExpandResult::errors`].
+pub fn expand<'a>(
+    config: &'a Config,
+    buffer: &'a str,
+    cursor: usize,
+    key: Option<ExpandKey>,
+) -> ExpandResult<'a> {
+    let found = match_snippets(config, buffer, cursor, key);
+    let mut errors = found.errors;
+
+    let expansions = found
+        .matches
+        .into_iter()
+        .filter_map(|(index, m, replacing_range)| {
+            let (left_snippet, right_snippet, has_placeholder) = if m.recursive() {
+                match recursion::expand_snippet(config, &m) {
+                    Ok((left, right, has_placeholder)) => {
+                        (Cow::from(left), Cow::from(right), has_placeholder)
+                    }
+                    Err(error) => {
+                        errors.push(SnippetError {
+                            name: m.name(),
+                            index,
+                            error: error.into(),
+                        });
+                        return None;
+                    }
+                }
+            } else {
+                (m.left_snippet(), m.right_snippet(), m.has_placeholder())
+            };
+
+            Some(Expansion {
+                replacing_range,
+                left_snippet,
+                right_snippet,
+                condition: m.condition(),
+                variables: m
+                    .captures
+                    .iter()
+                    .map(|c| ExpansionVariable {
+                        name: c.name.to_string(),
+                        value: c.value,
+                    })
+                    .collect(),
+                evaluate: m.evaluate(),
+                has_placeholder,
+                abort_on_error: m.abort_on_error(),
+                quote_captures: m.quote_captures(),
+                cache: m.cache(),
+                name: m.name(),
+                index,
+                file: m.file(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    for expansion in &expansions {
+        log::debug!(
+            "expansion '{}': {:?} -> {:?} + {:?}{}",
+            expansion.name,
+            &buffer[expansion.replacing_range.clone()],
+            expansion.left_snippet,
+            expansion.right_snippet,
+            expansion
+                .condition
+                .map(|c| format!(" if {c}"))
+                .unwrap_or_default()
+        );
     }
 
-    println!();
+    ExpandResult {
+        command: found.command,
+        last_arg: found.last_arg,
+        expansions,
+        errors,
+    }
 }
 
-fn expand<'a>(config: &'a Config, lbuffer: &'a str) -> ExpandResult<'a> {
-    let command = {
-        let command_index = find_last_command_index(lbuffer);
-        lbuffer[command_index..].trim_start()
-    };
+pub struct SnippetMatches<'a> {
+    pub command: &'a str,
+    pub last_arg: &'a str,
+    /// The range of the word under the cursor.
+    pub last_arg_range: Range<usize>,
+    /// The matched snippets with their indices in [`Config::snippets`] and the ranges of the buffer they replace.
+    pub matches: Vec<(usize, Match<'a>, Range<usize>)>,
+    /// Errors of the snippets skipped while matching.
+    pub errors: Vec<SnippetError<'a>>,
+}
 
-    let (_, last_arg) = command
-        .rsplit_once(char::is_whitespace)
-        .unwrap_or(("", command));
+/// Matches the snippets against the word under the cursor, skipping those that do not expand on `key`.
+pub fn match_snippets<'a>(
+    config: &'a Config,
+    buffer: &'a str,
+    cursor: usize,
+    key: Option<ExpandKey>,
+) -> SnippetMatches<'a> {
+    let start = Instant::now();
+    let parsed = parser::parse(buffer, cursor);
+    log::debug!("parsed {buffer:?} at {cursor} in {:?}", start.elapsed());
+
+    let command_index = parsed.command_index(&config.precommands());
+    let command_start_index = parsed.words[command_index].start;
+    let command_end_index = parsed.words[command_index].end();
+    let last_arg_start_index = parsed.cursor_word().start;
+    let last_arg_end_index = parsed.cursor_word().end();
+
+    let line = &buffer[parsed.words[0].start..last_arg_end_index];
+    let command = &buffer[command_start_index..last_arg_end_index];
+    let last_arg = parsed.cursor_word().text;
+    let last_arg_range = last_arg_start_index..last_arg_end_index;
 
     if last_arg.is_empty() {
-        return ExpandResult {
+        return SnippetMatches {
             command,
             last_arg,
-            expansions: Vec::new(),
+            last_arg_range,
+            matches: Vec::new(),
+            errors: Vec::new(),
         };
     }
 
-    let last_arg_start_index = lbuffer.len() - last_arg.len();
-    let command_start_index = lbuffer.len() - command.len();
-
-    let matches = find_matches(&config.snippets, command, last_arg);
-
-    let expansions = matches
+    let argv: Vec<_> = parsed.words[command_index..=parsed.cursor]
         .iter()
-        .map(|m| Expansion {
-            replacing_index: match m.action() {
-                Action::ReplaceLast => last_arg_start_index,
-                Action::ReplaceAll => command_start_index,
-            },
-            left_snippet: m.left_snippet(),
-            right_snippet: m.right_snippet(),
-            condition: m.condition(),
-            variables: m
-                .captures
-                .iter()
-                .map(|c| ExpansionVariable {
-                    name: c.name.to_string(),
-                    value: c.value,
-                })
-                .collect(),
-            evaluate: m.evaluate(),
-            has_placeholder: m.has_placeholder(),
-            abort_on_error: m.abort_on_error(),
+        .map(|w| w.text)
+        .collect();
+
+    let (matches, errors) = find_matches(&config.snippets, line, command, &argv, key);
+    let matches = matches
+        .into_iter()
+        .map(|(index, m)| {
+            let replacing_range = match m.action() {
+                Action::ReplaceLast => last_arg_start_index..last_arg_end_index,
+                Action::ReplaceAll => command_start_index..last_arg_end_index,
+                Action::ReplaceCommand => command_start_index..command_end_index,
+                Action::ReplaceLine => 0..buffer.len(),
+                Action::Prepend => command_start_index..command_start_index,
+                Action::Append => last_arg_end_index..last_arg_end_index,
+            };
+            (index, m, replacing_range)
         })
         .collect();
 
-    ExpandResult {
+    SnippetMatches {
         command,
         last_arg,
-        expansions,
+        last_arg_range,
+        matches,
+        errors,
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
     use super::*;
 
@@ -200,6 +308,20 @@ mod tests {
             context = "^apt "
             if = "(( ${+commands[apt]} ))"
 
+            [[snippets]]
+            name = "sudo"
+            trigger = "docker"
+            snippet = "sudo "
+            action = "prepend"
+
+            [[snippets]]
+            name = "jq ."
+            trigger-pattern = '\.json$'
+            snippet = "jq ."
+            action = "replace-command"
+            global = true
+            context = "^cat "
+
             [[snippets]]
             name = "trash"
             trigger = "rm"
@@ -227,6 +349,12 @@ mod tests {
             trigger-pattern = '^\.(?<n>\d+)$'
             snippet = "awk '{print \\$$n}'"
             evaluate = true
+
+            [[snippets]]
+            name = ",N"
+            trigger-pattern = '^,(?<n>\d+)$'
+            snippet = "cut -f{{ captures.n }}"
+            template = true
             "#,
         )
         .unwrap()
@@ -250,6 +378,7 @@ mod tests {
                     command: "",
                     last_arg: "",
                     expansions: Vec::new(),
+                    errors: Vec::new(),
                 },
             },
             Scenario {
@@ -259,15 +388,21 @@ mod tests {
                     command: "g",
                     last_arg: "g",
                     expansions: vec![Expansion {
-                        replacing_index: 0,
-                        left_snippet: "git",
-                        right_snippet: "",
+                        replacing_range: 0..1,
+                        left_snippet: "git".into(),
+                        right_snippet: "".into(),
                         condition: None,
                         variables: vec![],
                         evaluate: false,
                         has_placeholder: false,
                         abort_on_error: false,
+                        quote_captures: false,
+                        cache: None,
+                        name: "git",
+                        index: 0,
+                        file: None,
                     }],
+                    errors: Vec::new(),
                 },
             },
             Scenario {
@@ -277,15 +412,21 @@ mod tests {
                     command: "g",
                     last_arg: "g",
                     expansions: vec![Expansion {
-                        replacing_index: 12,
-                        left_snippet: "git",
-                        right_snippet: "",
+                        replacing_range: 12..13,
+                        left_snippet: "git".into(),
+                        right_snippet: "".into(),
                         condition: None,
                         variables: vec![],
                         evaluate: false,
                         has_placeholder: false,
                         abort_on_error: false,
+                        quote_captures: false,
+                        cache: None,
+                        name: "git",
+                        index: 0,
+                        file: None,
                     }],
+                    errors: Vec::new(),
                 },
             },
             Scenario {
@@ -295,15 +436,21 @@ mod tests {
                     command: "echo hello null",
                     last_arg: "null",
                     expansions: vec![Expansion {
-                        replacing_index: 11,
-                        left_snippet: ">/dev/null",
-                        right_snippet: "",
+                        replacing_range: 11..15,
+                        left_snippet: ">/dev/null".into(),
+                        right_snippet: "".into(),
                         condition: None,
                         variables: vec![],
                         evaluate: false,
                         has_placeholder: false,
                         abort_on_error: false,
+                        quote_captures: false,
+                        cache: None,
+                        name: ">/dev/null",
+                        index: 2,
+                        file: None,
                     }],
+                    errors: Vec::new(),
                 },
             },
             Scenario {
@@ -313,15 +460,21 @@ mod tests {
                     command: "git c",
                     last_arg: "c",
                     expansions: vec![Expansion {
-                        replacing_index: 16,
-                        left_snippet: "commit",
-                        right_snippet: "",
+                        replacing_range: 16..17,
+                        left_snippet: "commit".into(),
+                        right_snippet: "".into(),
                         condition: None,
                         variables: vec![],
                         evaluate: false,
                         has_placeholder: false,
                         abort_on_error: false,
+                        quote_captures: false,
+                        cache: None,
+                        name: "git commit",
+                        index: 1,
+                        file: None,
                     }],
+                    errors: Vec::new(),
                 },
             },
             Scenario {
@@ -331,6 +484,7 @@ mod tests {
                     command: "echo git c",
                     last_arg: "c",
                     expansions: Vec::new(),
+                    errors: Vec::new(),
                 },
             },
             Scenario {
@@ -340,6 +494,7 @@ mod tests {
                     command: "echo",
                     last_arg: "echo",
                     expansions: Vec::new(),
+                    errors: Vec::new(),
                 },
             },
             Scenario {
@@ -349,15 +504,21 @@ mod tests {
                     command: "home",
                     last_arg: "home",
                     expansions: vec![Expansion {
-                        replacing_index: 0,
-                        left_snippet: "$HOME",
-                        right_snippet: "",
+                        replacing_range: 0..4,
+                        left_snippet: "$HOME".into(),
+                        right_snippet: "".into(),
                         condition: None,
                         variables: vec![],
                         evaluate: true,
                         has_placeholder: false,
                         abort_on_error: false,
+                        quote_captures: false,
+                        cache: None,
+                        name: "$HOME",
+                        index: 3,
+                        file: None,
                     }],
+                    errors: Vec::new(),
                 },
             },
             Scenario {
@@ -367,15 +528,21 @@ mod tests {
                     command: "git cm",
                     last_arg: "cm",
                     expansions: vec![Expansion {
-                        replacing_index: 4,
-                        left_snippet: "commit -m '",
-                        right_snippet: "'",
+                        replacing_range: 4..6,
+                        left_snippet: "commit -m '".into(),
+                        right_snippet: "'".into(),
                         condition: None,
                         variables: vec![],
                         evaluate: false,
                         has_placeholder: true,
                         abort_on_error: false,
+                        quote_captures: false,
+                        cache: None,
+                        name: "git commit -m ''",
+                        index: 4,
+                        file: None,
                     }],
+                    errors: Vec::new(),
                 },
             },
             Scenario {
@@ -385,15 +552,69 @@ mod tests {
                     command: "apt install",
                     last_arg: "install",
                     expansions: vec![Expansion {
-                        replacing_index: 0,
-                        left_snippet: "sudo apt install -y",
-                        right_snippet: "",
+                        replacing_range: 0..11,
+                        left_snippet: "sudo apt install -y".into(),
+                        right_snippet: "".into(),
                         condition: Some("(( ${+commands[apt]} ))"),
                         variables: vec![],
                         evaluate: false,
                         has_placeholder: false,
                         abort_on_error: false,
+                        quote_captures: false,
+                        cache: None,
+                        name: "sudo apt install -y",
+                        index: 5,
+                        file: None,
+                    }],
+                    errors: Vec::new(),
+                },
+            },
+            Scenario {
+                testname: "prepend action for sudo",
+                lbuffer: "echo; docker",
+                expected: ExpandResult {
+                    command: "docker",
+                    last_arg: "docker",
+                    expansions: vec![Expansion {
+                        replacing_range: 6..6,
+                        left_snippet: "sudo ".into(),
+                        right_snippet: "".into(),
+                        condition: None,
+                        variables: vec![],
+                        evaluate: false,
+                        has_placeholder: false,
+                        abort_on_error: false,
+                        quote_captures: false,
+                        cache: None,
+                        name: "sudo",
+                        index: 6,
+                        file: None,
                     }],
+                    errors: Vec::new(),
+                },
+            },
+            Scenario {
+                testname: "replace-command action",
+                lbuffer: "cat a.json",
+                expected: ExpandResult {
+                    command: "cat a.json",
+                    last_arg: "a.json",
+                    expansions: vec![Expansion {
+                        replacing_range: 0..3,
+                        left_snippet: "jq .".into(),
+                        right_snippet: "".into(),
+                        condition: None,
+                        variables: vec![],
+                        evaluate: false,
+                        has_placeholder: false,
+                        abort_on_error: false,
+                        quote_captures: false,
+                        cache: None,
+                        name: "jq .",
+                        index: 7,
+                        file: None,
+                    }],
+                    errors: Vec::new(),
                 },
             },
             Scenario {
@@ -403,15 +624,21 @@ mod tests {
                     command: "..",
                     last_arg: "..",
                     expansions: vec![Expansion {
-                        replacing_index: 0,
-                        left_snippet: "cd $trigger",
-                        right_snippet: "",
+                        replacing_range: 0..2,
+                        left_snippet: "cd $trigger".into(),
+                        right_snippet: "".into(),
                         condition: None,
                         variables: vec![],
                         evaluate: true,
                         has_placeholder: false,
                         abort_on_error: false,
+                        quote_captures: false,
+                        cache: None,
+                        name: "cd ..",
+                        index: 11,
+                        file: None,
                     }],
+                    errors: Vec::new(),
                 },
             },
             Scenario {
@@ -421,15 +648,21 @@ mod tests {
                     command: "../..",
                     last_arg: "../..",
                     expansions: vec![Expansion {
-                        replacing_index: 5,
-                        left_snippet: "cd $trigger",
-                        right_snippet: "",
+                        replacing_range: 5..10,
+                        left_snippet: "cd $trigger".into(),
+                        right_snippet: "".into(),
                         condition: None,
                         variables: vec![],
                         evaluate: true,
                         has_placeholder: false,
                         abort_on_error: false,
+                        quote_captures: false,
+                        cache: None,
+                        name: "cd ..",
+                        index: 11,
+                        file: None,
                     }],
+                    errors: Vec::new(),
                 },
             },
             Scenario {
@@ -440,26 +673,37 @@ mod tests {
                     last_arg: "rm",
                     expansions: vec![
                         Expansion {
-                            replacing_index: 0,
-                            left_snippet: "trash",
-                            right_snippet: "",
+                            replacing_range: 0..2,
+                            left_snippet: "trash".into(),
+                            right_snippet: "".into(),
                             condition: Some("(( ${+commands[trash]} ))"),
                             variables: vec![],
                             evaluate: false,
                             has_placeholder: false,
                             abort_on_error: false,
+                            quote_captures: false,
+                            cache: None,
+                            name: "trash",
+                            index: 8,
+                            file: None,
                         },
                         Expansion {
-                            replacing_index: 0,
-                            left_snippet: "rm -r",
-                            right_snippet: "",
+                            replacing_range: 0..2,
+                            left_snippet: "rm -r".into(),
+                            right_snippet: "".into(),
                             condition: None,
                             variables: vec![],
                             evaluate: false,
                             has_placeholder: false,
                             abort_on_error: false,
+                            quote_captures: false,
+                            cache: None,
+                            name: "rm -r",
+                            index: 9,
+                            file: None,
                         },
                     ],
+                    errors: Vec::new(),
                 },
             },
             Scenario {
@@ -469,9 +713,9 @@ mod tests {
                     command: ".2",
                     last_arg: ".2",
                     expansions: vec![Expansion {
-                        replacing_index: 0,
-                        left_snippet: r"awk '{print \$$n}'",
-                        right_snippet: "",
+                        replacing_range: 0..2,
+                        left_snippet: r"awk '{print \$$n}'".into(),
+                        right_snippet: "".into(),
                         condition: None,
                         variables: vec![ExpansionVariable {
                             name: "n".to_string(),
@@ -480,49 +724,256 @@ mod tests {
                         evaluate: true,
                         has_placeholder: false,
                         abort_on_error: false,
+                        quote_captures: false,
+                        cache: None,
+                        name: ".N",
+                        index: 12,
+                        file: None,
                     }],
+                    errors: Vec::new(),
+                },
+            },
+            Scenario {
+                testname: "with template",
+                lbuffer: "cat a | ,3",
+                expected: ExpandResult {
+                    command: ",3",
+                    last_arg: ",3",
+                    expansions: vec![Expansion {
+                        replacing_range: 8..10,
+                        left_snippet: "cut -f3".into(),
+                        right_snippet: "".into(),
+                        condition: None,
+                        variables: vec![ExpansionVariable {
+                            name: "n".to_string(),
+                            value: "3",
+                        }],
+                        evaluate: false,
+                        has_placeholder: false,
+                        abort_on_error: false,
+                        quote_captures: false,
+                        cache: None,
+                        name: ",N",
+                        index: 13,
+                        file: None,
+                    }],
+                    errors: Vec::new(),
                 },
             },
         ];
 
         for s in scenarios {
-            let actual = expand(&config, s.lbuffer);
+            let actual = expand(&config, s.lbuffer, s.lbuffer.len(), None);
 
             assert_eq!(actual, s.expected, "{}", s.testname);
         }
     }
-}
 
-fn find_last_command_index(line: &str) -> usize {
-    line.rfind([';', '&', '|', '(', '`', '\n'])
-        .map(|i| i + 1)
-        .unwrap_or(0)
-}
+    #[test]
+    fn test_expand_on() {
+        let config = Config::load_from_str(
+            r#"
+            [[snippets]]
+            name = "rm -rf"
+            trigger = "rm"
+            snippet = "rm -rf"
+            expand-on = ["space"]
+
+            [[snippets]]
+            name = "rm -i"
+            trigger = "rm"
+            snippet = "rm -i"
+            expand-on = ["enter"]
+
+            [[snippets]]
+            name = ">/dev/null"
+            trigger = "null"
+            snippet = ">/dev/null"
+            global = true
+            "#,
+        )
+        .unwrap();
+
+        let scenarios = [
+            ("rm", None, Some("rm -rf")),
+            ("rm", Some(ExpandKey::Space), Some("rm -rf")),
+            ("rm", Some(ExpandKey::Enter), Some("rm -i")),
+            ("rm", Some(ExpandKey::Pipe), None),
+            ("echo null", Some(ExpandKey::Semicolon), Some(">/dev/null")),
+        ];
+
+        for (lbuffer, key, expected) in scenarios {
+            let actual = expand(&config, lbuffer, lbuffer.len(), key);
+            let actual = actual.expansions.first().map(|e| e.name);
+
+            assert_eq!(actual, expected, "{lbuffer} {key:?}");
+        }
+    }
 
-#[test]
-fn test_find_last_command_index() {
-    assert_eq!(find_last_command_index("git commit"), 0);
-    assert_eq!(find_last_command_index("echo hello; git commit"), 11);
-    assert_eq!(find_last_command_index("echo hello && git commit"), 13);
-    assert_eq!(find_last_command_index("seq 10 | tail -3 | cat"), 18);
+    #[test]
+    fn test_context_with_precommand() {
+        let config = Config::load_from_str(
+            r#"
+            [[snippets]]
+            name = "sudo apt install"
+            trigger = "i"
+            snippet = "install"
+            global = true
+            context = '^sudo apt '
+
+            [[snippets]]
+            name = "apt install"
+            trigger = "i"
+            snippet = "install"
+            global = true
+            context = '^apt '
+            "#,
+        )
+        .unwrap();
+
+        let scenarios = [
+            ("sudo apt i", Some(("sudo apt install", "apt i"))),
+            ("apt i", Some(("apt install", "apt i"))),
+            ("echo apt i", None),
+        ];
+
+        for (lbuffer, expected) in scenarios {
+            let actual = expand(&config, lbuffer, lbuffer.len(), None);
+            let actual = actual.expansions.first().map(|e| (e.name, actual.command));
+
+            assert_eq!(actual, expected, "{lbuffer}");
+        }
+    }
+
+    #[test]
+    fn test_clamp_cursor() {
+        let config = test_config();
+
+        let scenarios = [
+            ("g", 5, Some(("git", ""))),
+            ("é g", 1, None),
+            ("g é", 3, None),
+        ];
+
+        for (buffer, cursor, expected) in scenarios {
+            let result = expand(&config, buffer, cursor, None);
+            let actual = result.expansions.first().map(|e| e.apply(buffer, cursor));
+            let expected = expected.map(|(l, r): (&str, &str)| (l.to_string(), r.to_string()));
+
+            assert_eq!(actual, expected, "{buffer:?} at {cursor}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_serialize_non_utf8_file() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut config = Config::load_from_str(
+            r#"
+            [[snippets]]
+            trigger = "g"
+            snippet = "git"
+            "#,
+        )
+        .unwrap();
+        config.snippets[0].file = Some(OsStr::from_bytes(b"\xff.toml").into());
+
+        let result = expand(&config, "g", 1, None);
+        let json = serde_json::to_string(&result.expansions[0]).unwrap();
+        assert!(json.ends_with("\"file\":\"\u{fffd}.toml\"}"), "{json}");
+    }
+
+    #[test]
+    fn test_apply() {
+        let config = Config::load_from_str(
+            r#"
+            [[snippets]]
+            trigger = "g"
+            snippet = "git"
+
+            [[snippets]]
+            trigger = "cm"
+            snippet = "commit -m '{}'"
+            global = true
+
+            [[snippets]]
+            trigger = "null"
+            snippet = ">/dev/null"
+            global = true
+            action = "append"
+            "#,
+        )
+        .unwrap();
+
+        let scenarios = [
+            ("g", "", ("git", "")),
+            ("g", " status", ("git", " status")),
+            ("git cm", " --amend", ("git commit -m '", "' --amend")),
+            ("echo nu", "ll", ("echo null>/dev/null", "")),
+            ("echo null", "", ("echo null>/dev/null", "")),
+        ];
+
+        for (lbuffer, rbuffer, expected) in scenarios {
+            let buffer = format!("{lbuffer}{rbuffer}");
+            let result = expand(&config, &buffer, lbuffer.len(), None);
+            let (actual_lbuffer, actual_rbuffer) =
+                result.expansions[0].apply(&buffer, lbuffer.len());
+
+            assert_eq!(
+                (actual_lbuffer.as_str(), actual_rbuffer.as_str()),
+                expected,
+                "{lbuffer}|{rbuffer}"
+            );
+        }
+    }
 }
 
 fn find_matches<'a>(
     snippets: &'a [Snippet],
+    line: &'a str,
     command: &'a str,
-    last_arg: &'a str,
-) -> Vec<Match<'a>> {
+    argv: &[&'a str],
+    key: Option<ExpandKey>,
+) -> (Vec<(usize, Match<'a>)>, Vec<SnippetError<'a>>) {
     let mut matches = Vec::new();
-    for snippet in snippets {
-        if let Some(m) = snippet.do_match(command, last_arg) {
-            let has_condition = m.condition().is_some();
-            matches.push(m);
-
-            if !has_condition {
-                // Early break if m does not have condition.
-                break;
+    let mut errors = Vec::new();
+    for (index, snippet) in snippets
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.expands_on(key))
+    {
+        let start = Instant::now();
+        let result = snippet.do_match(line, command, argv);
+        log::debug!(
+            "snippet '{}': {} in {:?}",
+            snippet.display_name(),
+            match &result {
+                Ok(Some(_)) => "matched",
+                Ok(None) => "not matched",
+                Err(_) => "error",
+            },
+            start.elapsed()
+        );
+
+        match result {
+            Ok(Some(m)) => {
+                let has_condition = m.condition().is_some();
+                matches.push((index, m));
+
+                if !has_condition {
+                    // Early break if m does not have condition.
+                    break;
+                }
             }
+            Ok(None) => {}
+            Err(error) => errors.push(SnippetError {
+                name: snippet.display_name(),
+                index,
+                error,
+            }),
         }
     }
-    matches
+    (matches, errors)
 }
@@ -0,0 +1,305 @@
+use crate::config::Config;
+use crate::config::snippet::{Match, Snippet};
+use crate::expand::match_snippets;
+use std::ops::Range;
+use std::ptr;
+use thiserror::Error;
+
+const MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Error, Eq, PartialEq)]
+pub enum RecursionError {
+    #[error("recursive expansion cycle: {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+
+    #[error("recursive expansion is too deep: {}", .0.join(" -> "))]
+    TooDeep(Vec<String>),
+}
+
+/// Text with an optional placeholder position.
+#[derive(Debug)]
+struct Expanded {
+    text: String,
+    placeholder: Option<usize>,
+}
+
+/// Expands the triggers in the snippet of `m` again.
+///
+/// Returns `(left_snippet, right_snippet, has_placeholder)`.
+/// If the snippet itself has no placeholder, the first placeholder of the nested snippets is used.
+pub fn expand_snippet(
+    config: &Config,
+    m: &Match,
+) -> Result<(String, String, bool), RecursionError> {
+    let mut chain = vec![Link::new(m)];
+    let left = expand_text(config, &m.left_snippet(), &mut chain)?;
+    let right = expand_text(config, &m.right_snippet(), &mut chain)?;
+
+    match left.placeholder {
+        Some(p) if !m.has_placeholder() => {
+            let (left, right) = left.text.split_at(p);
+            Ok((left.to_string(), right.to_string(), true))
+        }
+        _ => Ok((left.text, right.text, m.has_placeholder())),
+    }
+}
+
+/// Expands every abbreviation in every command of `line`.
+pub fn expand_line(config: &Config, line: &str) -> Result<String, RecursionError> {
+    expand_text(config, line, &mut Vec::new()).map(|expanded| expanded.text)
+}
+
+/// A recursive snippet being expanded, identified by its address because names are not unique.
+struct Link {
+    snippet: *const Snippet,
+    name: String,
+}
+
+impl Link {
+    fn new(m: &Match) -> Self {
+        Self {
+            snippet: m.snippet(),
+            name: m.name().to_string(),
+        }
+    }
+}
+
+/// Returns the names of the snippets in `chain` followed by `last`.
+fn chain_names(chain: &[Link], last: Link) -> Vec<String> {
+    chain
+        .iter()
+        .chain([&last])
+        .map(|link| link.name.clone())
+        .collect()
+}
+
+/// Tokenises `text` and expands each word from left to right, as if the cursor were at its end.
+///
+/// Snippets with `if` or `evaluate` are left as they are, because they can only be resolved by the shell.
+fn expand_text(
+    config: &Config,
+    text: &str,
+    chain: &mut Vec<Link>,
+) -> Result<Expanded, RecursionError> {
+    let mut expanded = Expanded {
+        text: text.to_string(),
+        placeholder: None,
+    };
+
+    let mut cursor = 0;
+    while let Some(c) = expanded.text[cursor..].chars().next() {
+        cursor += c.len_utf8();
+
+        // Words end before a blank or an operator, so matching elsewhere is wasted
+        let at_word_end = expanded.text[cursor..]
+            .chars()
+            .next()
+            .is_none_or(|c| " \t\n;&|()<>`".contains(c));
+        if !at_word_end {
+            continue;
+        }
+
+        let (replacement, range) = match expand_word(config, &expanded.text, cursor, chain)? {
+            Some(r) => r,
+            None => continue,
+        };
+
+        let delta = |i: usize| i + replacement.text.len() - range.len();
+        expanded.placeholder = match expanded.placeholder {
+            Some(p) if p < range.start => Some(p),
+            Some(p) if p >= range.end => Some(delta(p)),
+            _ => replacement.placeholder.map(|p| range.start + p),
+        };
+        cursor = if cursor >= range.end {
+            delta(cursor)
+        } else {
+            range.start + replacement.text.len()
+        };
+        expanded.text.replace_range(range, &replacement.text);
+    }
+
+    Ok(expanded)
+}
+
+fn expand_word(
+    config: &Config,
+    text: &str,
+    cursor: usize,
+    chain: &mut Vec<Link>,
+) -> Result<Option<(Expanded, Range<usize>)>, RecursionError> {
+    let found = match_snippets(config, text, cursor, None);
+    if found.last_arg_range.end != cursor {
+        return Ok(None);
+    }
+
+    let (m, range) = match found.matches.into_iter().next() {
+        Some((_, m, range)) if m.condition().is_none() && !m.evaluate() => (m, range),
+        _ => return Ok(None),
+    };
+
+    let (left, right) = if m.recursive() {
+        let link = Link::new(&m);
+        if chain.iter().any(|l| ptr::eq(l.snippet, link.snippet)) {
+            return Err(RecursionError::Cycle(chain_names(chain, link)));
+        }
+        if chain.len() >= MAX_DEPTH {
+            return Err(RecursionError::TooDeep(chain_names(chain, link)));
+        }
+
+        chain.push(link);
+        let left = expand_text(config, &m.left_snippet(), chain)?;
+        let right = expand_text(config, &m.right_snippet(), chain)?;
+        chain.pop();
+        (left, right)
+    } else {
+        (
+            Expanded {
+                text: m.left_snippet().into_owned(),
+                placeholder: None,
+            },
+            Expanded {
+                text: m.right_snippet().into_owned(),
+                placeholder: None,
+            },
+        )
+    };
+
+    let placeholder = if m.has_placeholder() {
+        Some(left.text.len())
+    } else {
+        left.placeholder
+            .or(right.placeholder.map(|p| left.text.len() + p))
+    };
+
+    Ok(Some((
+        Expanded {
+            text: left.text + &right.text,
+            placeholder,
+        },
+        range,
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand_snippet() {
+        let config = Config::load_from_str(
+            r#"
+            [[snippets]]
+            name = "git"
+            trigger = "g"
+            snippet = "git"
+
+            [[snippets]]
+            name = "git commit -m ''"
+            trigger = "cm"
+            snippet = "commit -m '{}'"
+            global = true
+            context = '^git '
+
+            [[snippets]]
+            name = "git commit -m '' && git push"
+            trigger = "gcmp"
+            snippet = "gcm && g push"
+            recursive = true
+
+            [[snippets]]
+            name = "git commit -m ''"
+            trigger = "gcm"
+            snippet = "g cm"
+            recursive = true
+
+            [[snippets]]
+            name = "$HOME"
+            trigger = "home"
+            snippet = "$HOME"
+            evaluate = true
+
+            [[snippets]]
+            name = "ll"
+            trigger = "ll"
+            snippet = "ls -la home"
+            recursive = true
+
+            [[snippets]]
+            name = "same name"
+            trigger = "s1"
+            snippet = "s2"
+            recursive = true
+
+            [[snippets]]
+            name = "same name"
+            trigger = "s2"
+            snippet = "echo s"
+            recursive = true
+
+            [[snippets]]
+            name = "a"
+            trigger = "a"
+            snippet = "echo b"
+            global = true
+            recursive = true
+
+            [[snippets]]
+            name = "b"
+            trigger = "b"
+            snippet = "echo a"
+            global = true
+            recursive = true
+            "#,
+        )
+        .unwrap();
+
+        struct Scenario {
+            testname: &'static str,
+            trigger: &'static str,
+            expected: Result<(&'static str, &'static str, bool), RecursionError>,
+        }
+
+        let scenarios = &[
+            Scenario {
+                testname: "nested snippets",
+                trigger: "gcm",
+                expected: Ok(("git commit -m '", "'", true)),
+            },
+            Scenario {
+                testname: "nested recursive snippets",
+                trigger: "gcmp",
+                expected: Ok(("git commit -m '", "' && git push", true)),
+            },
+            Scenario {
+                testname: "evaluated snippets are not inlined",
+                trigger: "ll",
+                expected: Ok(("ls -la home", "", false)),
+            },
+            Scenario {
+                testname: "different snippets with the same name",
+                trigger: "s1",
+                expected: Ok(("echo s", "", false)),
+            },
+            Scenario {
+                testname: "cycle",
+                trigger: "a",
+                expected: Err(RecursionError::Cycle(vec![
+                    "a".to_string(),
+                    "b".to_string(),
+                    "a".to_string(),
+                ])),
+            },
+        ];
+
+        for s in scenarios {
+            let found = match_snippets(&config, s.trigger, s.trigger.len(), None);
+            let (_, m, _) = &found.matches[0];
+            let actual = expand_snippet(&config, m);
+            let expected = s
+                .expected
+                .clone()
+                .map(|(l, r, p)| (l.to_string(), r.to_string(), p));
+            assert_eq!(actual, expected, "{}", s.testname);
+        }
+    }
+}
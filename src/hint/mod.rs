@@ -0,0 +1,221 @@
+use crate::opt::HintArgs;
+use std::ptr;
+use zabrze::config::snippet::Action;
+use zabrze::config::{Config, Snippet, Trigger};
+use zabrze::expand::match_snippets;
+use zabrze::parser;
+
+#[derive(Debug, Eq, PartialEq)]
+struct Hint<'a> {
+    trigger: &'a str,
+    text: &'a str,
+}
+
+pub fn run(args: &HintArgs) {
+    // Errors are reported by the expansion, not before every command
+    let (config, _) = crate::load_config();
+
+    if let Some(hint) = find_hint(&config, &args.line, &args.expanded) {
+        println!(
+            "zabrze: tip: type \"{}\" for \"{}\"",
+            hint.trigger, hint.text
+        );
+    }
+}
+
+/// Finds the snippet that would have produced the longest text typed in `line`.
+///
+/// Snippets in `expanded` were already used while typing the line and are not hinted.
+fn find_hint<'a>(config: &'a Config, line: &str, expanded: &[String]) -> Option<Hint<'a>> {
+    let candidates: Vec<_> = config
+        .snippets
+        .iter()
+        .filter(|snippet| !expanded.iter().any(|e| e == snippet.display_name()))
+        .filter_map(|snippet| hint_text(snippet).map(|(trigger, text)| (snippet, trigger, text)))
+        .collect();
+    let mut best: Option<Hint> = None;
+
+    for (command_start, start) in parser::word_starts(line) {
+        for &(snippet, trigger, text) in &candidates {
+            if !starts_with_text(&line[start..], text, snippet)
+                || best
+                    .as_ref()
+                    .is_some_and(|b| saving(b) >= text.len() - trigger.len())
+            {
+                continue;
+            }
+
+            // The trigger must expand to the snippet at this position, which depends only on its command
+            let buffer = format!("{}{}", &line[command_start..start], trigger);
+            let found = match_snippets(config, &buffer, buffer.len(), None);
+            let is_expanded = found
+                .matches
+                .iter()
+                .any(|(_, m, _)| ptr::eq(m.snippet(), snippet));
+
+            if is_expanded {
+                best = Some(Hint { trigger, text });
+            }
+        }
+    }
+
+    best
+}
+
+/// Returns `(trigger, text)` if the text produced by the snippet is known without running it.
+fn hint_text(snippet: &Snippet) -> Option<(&str, &str)> {
+    let trigger = match &snippet.trigger {
+        Trigger::Text(trigger) => trigger,
+        Trigger::Regex(_) => return None,
+    };
+    if snippet.evaluate
+        || snippet.template
+        || snippet.recursive
+        || !matches!(snippet.action, Action::ReplaceLast)
+    {
+        return None;
+    }
+
+    let text = match snippet.cursor.as_deref().filter(|c| !c.is_empty()) {
+        Some(cursor) => snippet
+            .snippet
+            .split_once(cursor)
+            .map_or(snippet.snippet.as_str(), |(left, _)| left),
+        None => &snippet.snippet,
+    };
+
+    (text.trim().len() > trigger.len()).then_some((trigger, text))
+}
+
+fn saving(hint: &Hint) -> usize {
+    hint.text.len() - hint.trigger.len()
+}
+
+fn starts_with_text(rest: &str, text: &str, snippet: &Snippet) -> bool {
+    match rest.strip_prefix(text) {
+        // Snippets with a placeholder are followed by what was typed at the placeholder
+        Some(_) if text.len() < snippet.snippet.len() => true,
+        Some(after) => after.is_empty() || after.starts_with(char::is_whitespace),
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_hint() {
+        let config = Config::load_from_str(
+            r#"
+            [[snippets]]
+            name = "git"
+            trigger = "g"
+            snippet = "git"
+
+            [[snippets]]
+            name = "git commit"
+            trigger = "c"
+            snippet = "commit"
+            global = true
+            context = '^git\s'
+
+            [[snippets]]
+            name = "git commit -m ''"
+            trigger = "cm"
+            snippet = "commit -m '{}'"
+            global = true
+            context = '^git\s'
+
+            [[snippets]]
+            name = ">/dev/null"
+            trigger = "null"
+            snippet = ">/dev/null"
+            global = true
+
+            [[snippets]]
+            name = "$HOME"
+            trigger = "home"
+            snippet = "$HOME"
+            evaluate = true
+            "#,
+        )
+        .unwrap();
+
+        struct Scenario {
+            testname: &'static str,
+            line: &'static str,
+            expanded: &'static [&'static str],
+            expected: Option<Hint<'static>>,
+        }
+
+        let scenarios = &[
+            Scenario {
+                testname: "no hint",
+                line: "ls -la",
+                expanded: &[],
+                expected: None,
+            },
+            Scenario {
+                testname: "command",
+                line: "git status",
+                expanded: &[],
+                expected: Some(Hint {
+                    trigger: "g",
+                    text: "git",
+                }),
+            },
+            Scenario {
+                testname: "longest text",
+                line: "git commit -m 'fix'",
+                expanded: &[],
+                expected: Some(Hint {
+                    trigger: "cm",
+                    text: "commit -m '",
+                }),
+            },
+            Scenario {
+                testname: "context",
+                line: "echo commit",
+                expanded: &[],
+                expected: None,
+            },
+            Scenario {
+                testname: "expanded snippets",
+                line: "git commit; git push",
+                expanded: &["git commit"],
+                expected: Some(Hint {
+                    trigger: "g",
+                    text: "git",
+                }),
+            },
+            Scenario {
+                testname: "global",
+                line: "make >/dev/null 2>&1",
+                expanded: &[],
+                expected: Some(Hint {
+                    trigger: "null",
+                    text: ">/dev/null",
+                }),
+            },
+            Scenario {
+                testname: "partial word",
+                line: "gitk",
+                expanded: &[],
+                expected: None,
+            },
+            Scenario {
+                testname: "evaluated snippet",
+                line: "cd $HOME",
+                expanded: &[],
+                expected: None,
+            },
+        ];
+
+        for s in scenarios {
+            let expanded: Vec<_> = s.expanded.iter().map(|e| e.to_string()).collect();
+            let actual = find_hint(&config, s.line, &expanded);
+            assert_eq!(actual, s.expected, "{}", s.testname);
+        }
+    }
+}
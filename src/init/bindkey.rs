@@ -0,0 +1,244 @@
+use crate::opt::{InitArgs, Keymap};
+use shell_escape::escape;
+use std::borrow::Cow;
+use std::fmt::Write;
+
+/// Returns `keys`, or `defaults` if no key is specified.
+fn keys_or<'a>(keys: &'a [String], defaults: &'a [&'a str]) -> Vec<&'a str> {
+    if keys.is_empty() {
+        defaults.to_vec()
+    } else {
+        keys.iter().map(String::as_str).collect()
+    }
+}
+
+/// Returns the `bindkey` commands for `zabrze init --bind-keys`.
+pub fn zsh_script(args: &InitArgs) -> String {
+    // Expansion widgets chain to the widgets previously bound to their keys
+    let bindings = [
+        (
+            keys_or(&args.expand_keys, &[" "]),
+            "__zabrze::bindkey",
+            "__zabrze::expand-and-self-insert",
+        ),
+        (
+            keys_or(&args.accept_keys, &["^M"]),
+            "__zabrze::bindkey",
+            "__zabrze::expand-and-accept-line",
+        ),
+        (
+            keys_or(&args.insert_space_keys, &["^X "]),
+            "bindkey",
+            "__zabrze::insert-space",
+        ),
+        (
+            keys_or(&args.accept_line_keys, &["^X^M"]),
+            "bindkey",
+            "accept-line",
+        ),
+        (
+            keys_or(&args.revert_keys, &["^Xu"]),
+            "bindkey",
+            "__zabrze::revert",
+        ),
+    ];
+
+    let keymaps: Vec<_> = if args.keymaps.is_empty() {
+        vec![None]
+    } else {
+        args.keymaps.iter().map(|k| Some(zsh_keymap(*k))).collect()
+    };
+
+    let mut script = String::new();
+    for keymap in keymaps {
+        let keymap = keymap.map(|k| format!("-M {k} ")).unwrap_or_default();
+        for (keys, command, widget) in &bindings {
+            for key in keys {
+                let key = double_quote(key);
+                writeln!(script, "{command} {keymap}{key:<6} {widget}").unwrap();
+            }
+        }
+    }
+    script
+}
+
+/// Returns the `bind` commands for `zabrze init --shell bash --bind-keys`.
+///
+/// Keys are in the readline syntax (e.g. `\C-m`).
+pub fn bash_script(args: &InitArgs) -> String {
+    // `bind -x` cannot call readline commands, so accepting the line is a macro of the expansion and a private
+    // sequence bound to `accept-line`, which works even if the accept key is `\C-j`
+    let bindings = [
+        (
+            keys_or(&args.accept_keys, &[r"\C-m"]),
+            r#""\C-x\C-z\C-x\C-y""#,
+        ),
+        (keys_or(&args.insert_space_keys, &[r"\C-x "]), r#""\C-v ""#),
+        (
+            keys_or(&args.accept_line_keys, &[r"\C-x\C-m"]),
+            "accept-line",
+        ),
+    ];
+
+    let keymaps: Vec<_> = if args.keymaps.is_empty() {
+        vec![None]
+    } else {
+        args.keymaps.iter().map(|k| bash_keymap(*k)).collect()
+    };
+
+    let mut script = String::new();
+    for keymap in keymaps {
+        let keymap = keymap.map(|k| format!("-m {k} ")).unwrap_or_default();
+        let binding = escape(Cow::from(r#""\C-x\C-z": __zabrze_expand enter"#));
+        writeln!(script, "bind {keymap}-x {binding}").unwrap();
+        let binding = escape(Cow::from(r#""\C-x\C-y": accept-line"#));
+        writeln!(script, "bind {keymap}{binding}").unwrap();
+
+        // A key of a single character inserts itself, and other key sequences insert a space
+        for key in keys_or(&args.expand_keys, &[" "]) {
+            let text = match key.chars().count() {
+                1 => format!(" {}", double_quote(key)),
+                _ => String::new(),
+            };
+            let binding = format!(r#""{key}": __zabrze_expand_and_self_insert{text}"#);
+            writeln!(script, "bind {keymap}-x {}", escape(Cow::from(binding))).unwrap();
+        }
+
+        for (keys, command) in &bindings {
+            for key in keys {
+                let binding = escape(Cow::from(format!(r#""{key}": {command}"#)));
+                writeln!(script, "bind {keymap}{binding}").unwrap();
+            }
+        }
+    }
+    script
+}
+
+fn zsh_keymap(keymap: Keymap) -> &'static str {
+    match keymap {
+        Keymap::Main => "main",
+        Keymap::Emacs => "emacs",
+        Keymap::Viins => "viins",
+    }
+}
+
+/// Returns the readline keymap, or `None` for the current one.
+fn bash_keymap(keymap: Keymap) -> Option<&'static str> {
+    match keymap {
+        Keymap::Main => None,
+        Keymap::Emacs => Some("emacs"),
+        Keymap::Viins => Some("vi-insert"),
+    }
+}
+
+/// Double-quotes `key`, keeping escape sequences such as `\e` for `bindkey`.
+fn double_quote(key: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in key.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn init_args(args: &[&str]) -> InitArgs {
+        #[derive(Debug, clap::Parser)]
+        struct Cli {
+            #[command(flatten)]
+            args: InitArgs,
+        }
+
+        Cli::parse_from([&["zabrze"], args].concat()).args
+    }
+
+    #[test]
+    fn test_zsh_script() {
+        assert_eq!(
+            zsh_script(&init_args(&["--bind-keys"])),
+            r#"__zabrze::bindkey " "    __zabrze::expand-and-self-insert
+__zabrze::bindkey "^M"   __zabrze::expand-and-accept-line
+bindkey "^X "  __zabrze::insert-space
+bindkey "^X^M" accept-line
+bindkey "^Xu"  __zabrze::revert
+"#
+        );
+
+        assert_eq!(
+            zsh_script(&init_args(&[
+                "--bind-keys",
+                "--keymap=emacs",
+                "--keymap=viins",
+                "--expand-key= ",
+                "--expand-key=;",
+                "--insert-space-key=\\e ",
+                "--accept-line-key=^J",
+            ])),
+            r#"__zabrze::bindkey -M emacs " "    __zabrze::expand-and-self-insert
+__zabrze::bindkey -M emacs ";"    __zabrze::expand-and-self-insert
+__zabrze::bindkey -M emacs "^M"   __zabrze::expand-and-accept-line
+bindkey -M emacs "\\e " __zabrze::insert-space
+bindkey -M emacs "^J"   accept-line
+bindkey -M emacs "^Xu"  __zabrze::revert
+__zabrze::bindkey -M viins " "    __zabrze::expand-and-self-insert
+__zabrze::bindkey -M viins ";"    __zabrze::expand-and-self-insert
+__zabrze::bindkey -M viins "^M"   __zabrze::expand-and-accept-line
+bindkey -M viins "\\e " __zabrze::insert-space
+bindkey -M viins "^J"   accept-line
+bindkey -M viins "^Xu"  __zabrze::revert
+"#
+        );
+    }
+
+    #[test]
+    fn test_bash_script() {
+        assert_eq!(
+            bash_script(&init_args(&["--shell=bash", "--bind-keys"])),
+            r#"bind -x '"\C-x\C-z": __zabrze_expand enter'
+bind '"\C-x\C-y": accept-line'
+bind -x '" ": __zabrze_expand_and_self_insert " "'
+bind '"\C-m": "\C-x\C-z\C-x\C-y"'
+bind '"\C-x ": "\C-v "'
+bind '"\C-x\C-m": accept-line'
+"#
+        );
+
+        assert_eq!(
+            bash_script(&init_args(&[
+                "--shell=bash",
+                "--bind-keys",
+                "--keymap=viins",
+                "--expand-key= ",
+                "--expand-key=|",
+                "--expand-key=\\C-x\\C-e",
+                "--accept-key=\\C-j",
+            ])),
+            r#"bind -m vi-insert -x '"\C-x\C-z": __zabrze_expand enter'
+bind -m vi-insert '"\C-x\C-y": accept-line'
+bind -m vi-insert -x '" ": __zabrze_expand_and_self_insert " "'
+bind -m vi-insert -x '"|": __zabrze_expand_and_self_insert "|"'
+bind -m vi-insert -x '"\C-x\C-e": __zabrze_expand_and_self_insert'
+bind -m vi-insert '"\C-j": "\C-x\C-z\C-x\C-y"'
+bind -m vi-insert '"\C-x ": "\C-v "'
+bind -m vi-insert '"\C-x\C-m": accept-line'
+"#
+        );
+
+        // The main keymap is the current one, as in `bind` without `-m`
+        assert_eq!(
+            bash_script(&init_args(&[
+                "--shell=bash",
+                "--bind-keys",
+                "--keymap=main"
+            ])),
+            bash_script(&init_args(&["--shell=bash", "--bind-keys"])),
+        );
+    }
+}
@@ -1,12 +1,15 @@
+mod bindkey;
+
 use std::path::Path;
 
 use crate::{
     config::{self, Config},
-    opt::InitArgs,
+    opt::{InitArgs, Shell},
 };
 
 static INIT_SCRIPT: &str = include_str!("zabrze-init.zsh");
-static BIND_KEYS_SCRIPT: &str = include_str!("zabrze-bindkey.zsh");
+static HINT_SCRIPT: &str = include_str!("zabrze-hint.zsh");
+static BASH_INIT_SCRIPT: &str = include_str!("zabrze-init.bash");
 
 fn warn_yaml_config_deprecation() {
     if let Some(config_dir) = config::get_default_config_dir()
@@ -35,9 +38,32 @@ fn warn_yaml_config_deprecation() {
 pub fn run(args: &InitArgs) {
     warn_yaml_config_deprecation();
 
-    print!("{INIT_SCRIPT}");
+    match args.shell {
+        Shell::Zsh => {
+            print!("{INIT_SCRIPT}");
+
+            if args.bind_keys {
+                print!("{}", bindkey::zsh_script(args));
+            }
+
+            if args.hint {
+                print!("{HINT_SCRIPT}");
+            }
+        }
+        Shell::Bash => {
+            print!("{BASH_INIT_SCRIPT}");
+
+            if args.bind_keys {
+                print!("{}", bindkey::bash_script(args));
+            }
 
-    if args.bind_keys {
-        print!("{BIND_KEYS_SCRIPT}");
+            if args.hint {
+                eprintln!(
+                    "{}",
+                    ansi_term::Color::Yellow
+                        .paint("zabrze: Warning: --hint is not supported in bash.")
+                );
+            }
+        }
     }
 }
@@ -0,0 +1,58 @@
+# Expands $1 as if it were in double quotes, like the (e) flag of zsh
+__zabrze_eval() {
+  local __zabrze_rest="$1" __zabrze_quoted="" __zabrze_value
+  while [[ -n "$__zabrze_rest" ]]; do
+    case "$__zabrze_rest" in
+      # An escaped character is kept as is, and a trailing backslash is a literal one
+      \\?*) __zabrze_quoted+="${__zabrze_rest:0:2}"; __zabrze_rest="${__zabrze_rest:2}"; continue ;;
+      \\) __zabrze_quoted+='\\' ;;
+      \"*) __zabrze_quoted+='\"' ;;
+      *) __zabrze_quoted+="${__zabrze_rest:0:1}" ;;
+    esac
+    __zabrze_rest="${__zabrze_rest:1}"
+  done
+  eval "__zabrze_value=\"${__zabrze_quoted}\"" && printf '%s' "$__zabrze_value"
+}
+
+# The PID and the start time, so that a shell reusing the PID does not see the session caches
+printf -v __zabrze_session '%st%(%s)T' "$$" -1
+
+__zabrze_expand() {
+  local out __zabrze_error
+  out="$(zabrze expand --shell=bash --buffer="$READLINE_LINE" --cursor="$READLINE_POINT" --session="$__zabrze_session" --error-output=variable ${1:+--key="$1"})" || return
+  if [[ -n "$out" ]]; then
+    eval "$out"
+  fi
+  if [[ -n "$__zabrze_error" ]]; then
+    __zabrze_report_error "$__zabrze_error"
+  fi
+}
+
+# Each error is reported once per session, to stderr or to $ZABRZE_ERROR_LOG
+declare -gA __zabrze_reported_errors
+__zabrze_report_error() {
+  [[ -n "${__zabrze_reported_errors["$1"]+set}" ]] && return
+  __zabrze_reported_errors["$1"]=1
+  if [[ -n "$ZABRZE_ERROR_LOG" ]]; then
+    printf '%(%Y-%m-%dT%H:%M:%S)T %s\n' -1 "$1" 2>/dev/null >>"$ZABRZE_ERROR_LOG"
+  else
+    printf 'zabrze: %s\n' "$1" >&2
+  fi
+}
+
+# Usage: __zabrze_expand_and_self_insert [text]
+__zabrze_expand_and_self_insert() {
+  local text="${1- }" key
+  case "$text" in
+    " ") key=space ;;
+    "|") key=pipe ;;
+    ";") key=semicolon ;;
+    "&") key=ampersand ;;
+  esac
+  __zabrze_expand "$key"
+  if [[ -z "$__zabrze_has_placeholder" ]]; then
+    READLINE_LINE="${READLINE_LINE:0:READLINE_POINT}${text}${READLINE_LINE:READLINE_POINT}"
+    READLINE_POINT=$((READLINE_POINT + ${#text}))
+  fi
+  unset __zabrze_has_placeholder
+}
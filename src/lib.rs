@@ -0,0 +1,26 @@
+//! Abbreviation expansion for zsh and bash.
+//!
+//! This crate is the matcher behind the `zabrze` command, for embedding in other frontends.
+//! It never prints or exits; errors are returned to the caller.
+//!
+//! ```
+//! use zabrze::config::Config;
+//! use zabrze::expand::expand;
+//!
+//! let config = Config::load_from_str(
+//!     r#"
+//!     [[snippets]]
+//!     trigger = "g"
+//!     snippet = "git"
+//!     "#,
+//! )
+//! .unwrap();
+//!
+//! let result = expand(&config, "g", 1, None);
+//! assert_eq!(result.expansions[0].left_snippet, "git");
+//! assert_eq!(result.expansions[0].replacing_range, 0..1);
+//! ```
+
+pub mod config;
+pub mod expand;
+pub mod parser;
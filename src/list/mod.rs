@@ -1,30 +1,81 @@
-use crate::config::{Config, Trigger};
-use crate::opt::ListArgs;
+use crate::opt::{ListArgs, ListFormat};
 use shell_escape::escape;
 use std::borrow::Cow;
 use std::io;
+use zabrze::config::{Config, Snippet, Trigger};
 
 pub fn run(args: &ListArgs) {
-    list(args, &Config::load_or_exit(), &mut io::stdout()).unwrap();
+    let (config, errors) = crate::load_config();
+    let mut out = Vec::new();
+    list(args, &config, &mut out).unwrap();
+    crate::print_output(
+        args.error_output,
+        Some(&String::from_utf8_lossy(&out)),
+        &errors,
+    );
 }
 
-fn list<W: io::Write>(_args: &ListArgs, config: &Config, out: &mut W) -> Result<(), io::Error> {
-    for snippet in &config.snippets {
-        let trigger = match &snippet.trigger {
-            Trigger::Text(text) => text,
-            Trigger::Regex(regex) => regex,
-        };
-        let snippet = escape(Cow::from(&snippet.snippet));
+fn list<W: io::Write>(args: &ListArgs, config: &Config, out: &mut W) -> Result<(), io::Error> {
+    let snippets = config
+        .snippets
+        .iter()
+        .filter(|snippet| args.tags.iter().all(|tag| snippet.tags.contains(tag)));
 
-        writeln!(out, "{}={}", trigger, snippet)?;
+    for snippet in snippets {
+        match args.format {
+            ListFormat::Text => write_text(snippet, out)?,
+            ListFormat::Completion => write_completion(snippet, out)?,
+        }
     }
 
     Ok(())
 }
 
+/// Writes `trigger=snippet  # description [tag, ...]`.
+fn write_text<W: io::Write>(snippet: &Snippet, out: &mut W) -> Result<(), io::Error> {
+    let trigger = match &snippet.trigger {
+        Trigger::Text(text) => text,
+        Trigger::Regex(regex) => regex,
+    };
+    write!(out, "{}={}", trigger, escape(Cow::from(&snippet.snippet)))?;
+
+    let mut comment = Vec::new();
+    if let Some(description) = &snippet.description {
+        comment.push(one_line(description));
+    }
+    if !snippet.tags.is_empty() {
+        comment.push(format!("[{}]", snippet.tags.join(", ")));
+    }
+    if !comment.is_empty() {
+        write!(out, "  # {}", comment.join(" "))?;
+    }
+
+    writeln!(out)
+}
+
+/// Writes `trigger:description` for `_describe`, falling back to the name. Patterns cannot be completed.
+fn write_completion<W: io::Write>(snippet: &Snippet, out: &mut W) -> Result<(), io::Error> {
+    let trigger = match &snippet.trigger {
+        Trigger::Text(text) => text,
+        Trigger::Regex(_) => return Ok(()),
+    };
+    let trigger = trigger.replace('\\', "\\\\").replace(':', "\\:");
+    let description = snippet
+        .description
+        .as_deref()
+        .unwrap_or_else(|| snippet.display_name());
+
+    writeln!(out, "{}:{}", trigger, one_line(description))
+}
+
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::opt::ErrorOutput;
 
     fn test_config() -> Config {
         Config::load_from_str(
@@ -57,6 +108,21 @@ mod tests {
             trigger-pattern = '^\.\.(/\.\.)*$'
             snippet = "cd $trigger"
             evaluate = true
+
+            [[snippets]]
+            name = "kubectl"
+            description = """
+            Run kubectl
+            in the current context"""
+            tags = ["k8s"]
+            trigger = "k"
+            snippet = "kubectl"
+
+            [[snippets]]
+            description = "Switch namespace"
+            tags = ["k8s", "ns"]
+            trigger = "k:ns"
+            snippet = "kubectl config set-context --current --namespace"
             "#,
         )
         .unwrap()
@@ -64,21 +130,54 @@ mod tests {
 
     #[test]
     fn test_list() {
-        let args = ListArgs {};
-        let config = test_config();
-
-        let mut buf = Vec::new();
-        list(&args, &config, &mut std::io::BufWriter::new(&mut buf)).unwrap();
-
-        let output = std::str::from_utf8(&buf).unwrap();
-
-        let expected = r"g=git
+        let scenarios = &[
+            (
+                ListArgs {
+                    tags: vec![],
+                    format: ListFormat::Text,
+                    error_output: ErrorOutput::Stderr,
+                },
+                r"g=git
 c=commit
 null='>/dev/null'
 home='$HOME'
 ^\.\.(/\.\.)*$='cd $trigger'
-";
+k=kubectl  # Run kubectl in the current context [k8s]
+k:ns='kubectl config set-context --current --namespace'  # Switch namespace [k8s, ns]
+",
+            ),
+            (
+                ListArgs {
+                    tags: vec!["k8s".to_string(), "ns".to_string()],
+                    format: ListFormat::Text,
+                    error_output: ErrorOutput::Stderr,
+                },
+                "k:ns='kubectl config set-context --current --namespace'  # Switch namespace [k8s, ns]\n",
+            ),
+            (
+                ListArgs {
+                    tags: vec![],
+                    format: ListFormat::Completion,
+                    error_output: ErrorOutput::Stderr,
+                },
+                r"g:git
+c:git commit
+null:>/dev/null
+home:$HOME
+k:Run kubectl in the current context
+k\:ns:Switch namespace
+",
+            ),
+        ];
+
+        let config = test_config();
+
+        for (args, expected) in scenarios {
+            let mut buf = Vec::new();
+            list(args, &config, &mut std::io::BufWriter::new(&mut buf)).unwrap();
 
-        assert_eq!(output, expected);
+            let output = std::str::from_utf8(&buf).unwrap();
+            assert_eq!(output, *expected, "{args:?}");
+        }
     }
 }
@@ -0,0 +1,123 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Writes log records to `$ZABRZE_LOG_FILE`, or stderr if it is not set.
+struct Logger {
+    level: LevelFilter,
+    file: Option<Mutex<File>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = format_record(record, SystemTime::now());
+        match &self.file {
+            Some(file) => {
+                if let Ok(mut file) = file.lock() {
+                    let _ = file.write_all(line.as_bytes());
+                }
+            }
+            None => eprint!("{line}"),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// Enables logging at the level of `$ZABRZE_LOG` (`error`, `warn`, `info`, `debug` or `trace`).
+///
+/// Logs are written to `$ZABRZE_LOG_FILE`, or to stderr if `stderr` is `true`.
+pub fn init(stderr: bool) {
+    let level = parse_level(env::var("ZABRZE_LOG").ok().as_deref());
+    if level == LevelFilter::Off {
+        return;
+    }
+
+    let file = match env::var_os("ZABRZE_LOG_FILE") {
+        Some(path) => match OpenOptions::new().create(true).append(true).open(&path) {
+            Ok(file) => Some(Mutex::new(file)),
+            Err(error) if stderr => {
+                let path = Path::new(&path).display();
+                let error = format!("failed to open ZABRZE_LOG_FILE '{path}': {error}");
+                crate::print_errors(&[error]);
+                None
+            }
+            Err(_) => return,
+        },
+        None if stderr => None,
+        None => return,
+    };
+
+    if log::set_boxed_logger(Box::new(Logger { level, file })).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+fn parse_level(level: Option<&str>) -> LevelFilter {
+    level
+        .and_then(|level| level.parse().ok())
+        .unwrap_or(LevelFilter::Off)
+}
+
+/// Formats `record` as `<seconds>.<millis> <pid> <LEVEL> <target>: <message>`.
+fn format_record(record: &Record, time: SystemTime) -> String {
+    let time = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    format!(
+        "{}.{:03} {} {:<5} {}: {}\n",
+        time.as_secs(),
+        time.subsec_millis(),
+        process::id(),
+        record.level(),
+        record.target(),
+        record.args(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_level() {
+        assert_eq!(parse_level(None), LevelFilter::Off);
+        assert_eq!(parse_level(Some("debug")), LevelFilter::Debug);
+        assert_eq!(parse_level(Some("TRACE")), LevelFilter::Trace);
+        assert_eq!(parse_level(Some("verbose")), LevelFilter::Off);
+    }
+
+    #[test]
+    fn test_format_record() {
+        let time = UNIX_EPOCH + Duration::from_millis(1700000000042);
+        let record = |args| {
+            let record = Record::builder()
+                .level(Level::Debug)
+                .target("zabrze::expand")
+                .args(args)
+                .build();
+            format_record(&record, time)
+        };
+
+        assert_eq!(
+            record(format_args!("parsed in {:?}", Duration::from_micros(12))),
+            format!(
+                "1700000000.042 {} DEBUG zabrze::expand: parsed in 12µs\n",
+                process::id()
+            )
+        );
+    }
+}
@@ -1,18 +1,103 @@
-mod config;
-mod expand;
+mod hint;
 mod init;
 mod list;
+mod logger;
 mod opt;
+mod script;
+mod stats;
+mod suggest;
+mod test;
 
+use ansi_term::Color;
 use clap::Parser;
-use opt::{Opt, Subcommand};
+use opt::{ErrorOutput, Opt, Subcommand};
+use shell_escape::escape;
+use std::borrow::Cow;
+use std::path::Path;
+use zabrze::config::{self, Config};
 
 fn main() {
     let opt = Opt::parse();
 
+    // In the line editor, stderr would be drawn over the prompt
+    let error_output = match &opt.subcommand {
+        Subcommand::Expand(args) => args.error_output,
+        Subcommand::ExpandLine(args) => args.error_output,
+        Subcommand::List(args) => args.error_output,
+        _ => ErrorOutput::Stderr,
+    };
+    let in_line_editor = error_output == ErrorOutput::Variable;
+    logger::init(!in_line_editor);
+
     match &opt.subcommand {
         Subcommand::Init(args) => init::run(args),
         Subcommand::List(args) => list::run(args),
-        Subcommand::Expand(args) => expand::run(args),
+        Subcommand::Expand(args) => script::run(args),
+        Subcommand::ExpandLine(args) => script::run_line(args),
+        Subcommand::Stats(args) => stats::run(args),
+        Subcommand::Suggest(args) => suggest::run(args),
+        Subcommand::Hint(args) => hint::run(args),
+        Subcommand::Test(args) => test::run(args),
+    }
+}
+
+/// Loads the config files, returning the errors instead of printing them.
+pub fn load_config() -> (Config, Vec<String>) {
+    let Some(config_dir) = config::get_default_config_dir() else {
+        let error_message = "could not determine config directory".to_string();
+        return (Config::default(), vec![error_message]);
+    };
+
+    match Config::load_from_dir(Path::new(&config_dir)) {
+        Ok((config, errors)) => (config, errors.iter().map(ToString::to_string).collect()),
+        Err(error) => {
+            let error_message = format!("failed to read config directory: {error}");
+            (Config::default(), vec![error_message])
+        }
+    }
+}
+
+/// Loads the config files, printing the errors of those that fail to load.
+pub fn load_config_or_warn() -> Config {
+    let (config, errors) = load_config();
+    print_errors(&errors);
+    config
+}
+
+pub fn print_errors(errors: &[String]) {
+    for error in errors {
+        eprintln!("{}", Color::Red.normal().paint(error));
+    }
+}
+
+/// Returns shell code that assigns the errors to `$__zabrze_error`, or nothing without errors.
+pub fn error_script(errors: &[String]) -> String {
+    if errors.is_empty() {
+        return String::new();
+    }
+    let error = escape(Cow::from(errors.join("\n")));
+    format!("__zabrze_error={error};")
+}
+
+/// Prints `output` and reports `errors` to stderr, or prints shell code that assigns them to `$__zabrze_output`
+/// and `$__zabrze_error`.
+///
+/// The trailing newline of `output` is not assigned, as in `$(...)`.
+pub fn print_output(error_output: ErrorOutput, output: Option<&str>, errors: &[String]) {
+    match error_output {
+        ErrorOutput::Stderr => {
+            print_errors(errors);
+            if let Some(output) = output {
+                print!("{output}");
+            }
+        }
+        ErrorOutput::Variable => {
+            let mut script = error_script(errors);
+            if let Some(output) = output {
+                let output = output.strip_suffix('\n').unwrap_or(output);
+                script += &format!("__zabrze_output={};", escape(Cow::from(output)));
+            }
+            println!("{script}");
+        }
     }
 }
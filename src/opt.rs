@@ -1,3 +1,6 @@
+use std::path::PathBuf;
+use zabrze::config::snippet::ExpandKey;
+
 #[derive(Debug, clap::Parser)]
 #[command(version, disable_version_flag = true, author, about)]
 pub struct Opt {
@@ -18,22 +21,273 @@ pub enum Subcommand {
 
     #[command(about = "Expand abbreviation")]
     Expand(ExpandArgs),
+
+    #[command(about = "Expand all abbreviations in a command line")]
+    ExpandLine(ExpandLineArgs),
+
+    #[command(about = "Show usage statistics of abbreviations")]
+    Stats(StatsArgs),
+
+    #[command(about = "Suggest abbreviations from the shell history")]
+    Suggest(SuggestArgs),
+
+    #[command(about = "Show a tip when a command line has an abbreviation")]
+    Hint(HintArgs),
+
+    #[command(about = "Run the tests of abbreviations in the config files")]
+    Test(TestArgs),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    Zsh,
+    Bash,
 }
 
 #[derive(Debug, clap::Args)]
 pub struct InitArgs {
+    #[arg(help = "Shell to initialize", long, value_enum, default_value_t = Shell::Zsh)]
+    pub shell: Shell,
+
     #[arg(help = "Enable default key bindings", long)]
     pub bind_keys: bool,
+
+    #[arg(help = "Show a tip when an executed command has an abbreviation", long)]
+    pub hint: bool,
+
+    #[arg(
+        help = "Key that expands and inserts itself (default: space)",
+        long = "expand-key",
+        value_name = "KEY",
+        requires = "bind_keys"
+    )]
+    pub expand_keys: Vec<String>,
+
+    #[arg(
+        help = "Key that expands and accepts the line (default: ^M)",
+        long = "accept-key",
+        value_name = "KEY",
+        requires = "bind_keys"
+    )]
+    pub accept_keys: Vec<String>,
+
+    #[arg(
+        help = "Key that inserts a space without expansion (default: ^X space)",
+        long = "insert-space-key",
+        value_name = "KEY",
+        requires = "bind_keys"
+    )]
+    pub insert_space_keys: Vec<String>,
+
+    #[arg(
+        help = "Key that accepts the line without expansion (default: ^X^M)",
+        long = "accept-line-key",
+        value_name = "KEY",
+        requires = "bind_keys"
+    )]
+    pub accept_line_keys: Vec<String>,
+
+    #[arg(
+        help = "Key that reverts the last expansion (default: ^Xu)",
+        long = "revert-key",
+        value_name = "KEY",
+        requires = "bind_keys"
+    )]
+    pub revert_keys: Vec<String>,
+
+    #[arg(
+        help = "Keymap to bind the keys in (default: the current keymap)",
+        long = "keymap",
+        value_enum,
+        requires = "bind_keys"
+    )]
+    pub keymaps: Vec<Keymap>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Keymap {
+    Main,
+    Emacs,
+    Viins,
 }
 
 #[derive(Debug, clap::Args)]
-pub struct ListArgs {}
+pub struct ListArgs {
+    #[arg(
+        help = "Only list abbreviations with the tag",
+        long = "tag",
+        value_name = "TAG"
+    )]
+    pub tags: Vec<String>,
+
+    #[arg(help = "Output format", long, value_enum, default_value_t = ListFormat::Text)]
+    pub format: ListFormat,
+
+    #[arg(
+        help = "Where to report errors (`variable` prints shell code that assigns the list to $__zabrze_output)",
+        long,
+        value_enum,
+        default_value_t = ErrorOutput::Stderr
+    )]
+    pub error_output: ErrorOutput,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ListFormat {
+    /// `trigger=snippet` followed by the description and tags
+    Text,
+    /// `trigger:description` for `_describe` of zsh
+    Completion,
+}
 
 #[derive(Debug, clap::Args)]
 pub struct ExpandArgs {
-    #[arg(help = "$LBUFFER", long, short = 'l')]
-    pub lbuffer: String,
+    #[arg(
+        help = "$LBUFFER",
+        long,
+        short = 'l',
+        required_unless_present = "buffer"
+    )]
+    pub lbuffer: Option<String>,
+
+    #[arg(
+        help = "$RBUFFER",
+        long,
+        short = 'r',
+        required_unless_present = "buffer"
+    )]
+    pub rbuffer: Option<String>,
+
+    #[arg(help = "$BUFFER", long, short = 'b', conflicts_with_all = ["lbuffer", "rbuffer"], requires = "cursor")]
+    pub buffer: Option<String>,
+
+    #[arg(help = "$CURSOR", long, short = 'c', requires = "buffer")]
+    pub cursor: Option<usize>,
+
+    #[arg(
+        help = "Session ID used for `cache = \"session\"`, unique to the shell process",
+        long
+    )]
+    pub session: Option<String>,
+
+    #[arg(
+        help = "Key typed to expand, matched against `expand-on`",
+        long,
+        value_enum
+    )]
+    pub key: Option<Key>,
+
+    #[arg(help = "Shell to generate code for", long, value_enum, default_value_t = Shell::Zsh)]
+    pub shell: Shell,
 
-    #[arg(help = "$RBUFFER", long, short = 'r')]
-    pub rbuffer: String,
+    #[arg(help = "Output format", long, value_enum, default_value_t = Format::Shell)]
+    pub format: Format,
+
+    #[arg(
+        help = "Where to report errors in the shell format",
+        long,
+        value_enum,
+        default_value_t = ErrorOutput::Stderr
+    )]
+    pub error_output: ErrorOutput,
+}
+
+/// [`ExpandKey`] of the library, as a command line argument.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Key {
+    Space,
+    Enter,
+    Pipe,
+    Semicolon,
+    Ampersand,
+}
+
+impl From<Key> for ExpandKey {
+    fn from(key: Key) -> Self {
+        match key {
+            Key::Space => ExpandKey::Space,
+            Key::Enter => ExpandKey::Enter,
+            Key::Pipe => ExpandKey::Pipe,
+            Key::Semicolon => ExpandKey::Semicolon,
+            Key::Ampersand => ExpandKey::Ampersand,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ErrorOutput {
+    /// Print the errors to stderr
+    Stderr,
+    /// Assign the errors to $__zabrze_error in the shell code
+    Variable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Shell code that updates the buffer
+    Shell,
+    /// The matched snippets, not evaluated
+    Json,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ExpandLineArgs {
+    #[arg(help = "Command line to expand")]
+    pub line: String,
+
+    #[arg(
+        help = "Where to report errors (`variable` prints shell code that assigns the line to $__zabrze_output)",
+        long,
+        value_enum,
+        default_value_t = ErrorOutput::Stderr
+    )]
+    pub error_output: ErrorOutput,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct StatsArgs {
+    #[arg(
+        help = "Report snippets unused in the last N days",
+        long,
+        default_value_t = 30
+    )]
+    pub days: u64,
+
+    #[arg(
+        help = "Number of most used triggers to show",
+        long,
+        default_value_t = 10
+    )]
+    pub top: usize,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct SuggestArgs {
+    #[arg(help = "Path to the zsh history file (e.g. ~/.zsh_history)", long)]
+    pub history: PathBuf,
+
+    #[arg(help = "Maximum number of suggestions", long, default_value_t = 10)]
+    pub limit: usize,
+
+    #[arg(
+        help = "Minimum number of times a command must be typed",
+        long,
+        default_value_t = 3
+    )]
+    pub min_count: usize,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct HintArgs {
+    #[arg(help = "Executed command line", long)]
+    pub line: String,
+
+    #[arg(help = "Names of the snippets expanded while typing the line")]
+    pub expanded: Vec<String>,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct TestArgs {
+    #[arg(help = "Only run the tests of abbreviations whose name contains FILTER")]
+    pub filter: Option<String>,
 }
@@ -0,0 +1,700 @@
+/// A word of the command line, such as `git`, `'a b'` or `"$(pwd)"`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Word<'a> {
+    pub text: &'a str,
+    pub start: usize,
+}
+
+impl Word<'_> {
+    pub fn end(&self) -> usize {
+        self.start + self.text.len()
+    }
+}
+
+/// The command that the cursor is placed in.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Command<'a> {
+    /// The words of the command. The last word is the one under the cursor, which may be empty.
+    pub words: Vec<Word<'a>>,
+    /// The index of the word under the cursor.
+    pub cursor: usize,
+}
+
+impl<'a> Command<'a> {
+    pub fn cursor_word(&self) -> &Word<'a> {
+        &self.words[self.cursor]
+    }
+
+    /// Returns the index of the word in command position, skipping leading assignments,
+    /// precommand modifiers and their options (e.g. `FOO=1 sudo -u root git` → `git`).
+    pub fn command_index<S: AsRef<str>>(&self, precommands: &[S]) -> usize {
+        let mut precommand = None;
+        let mut skip_argument = false;
+        for (i, word) in self.words[..self.cursor].iter().enumerate() {
+            if skip_argument {
+                skip_argument = false;
+                continue;
+            }
+            if let Some(precommand) = precommand
+                && word.text.starts_with('-')
+            {
+                skip_argument = takes_argument(precommand, word.text);
+                continue;
+            }
+            if precommands.iter().any(|p| p.as_ref() == word.text) {
+                precommand = Some(word.text);
+                continue;
+            }
+            if is_assignment(word.text) {
+                continue;
+            }
+            return i;
+        }
+        self.cursor
+    }
+}
+
+/// Options of precommand modifiers that take a separate argument.
+static OPTIONS_WITH_ARGUMENT: &[(&str, &[&str])] = &[
+    (
+        "sudo",
+        &[
+            "-u",
+            "-g",
+            "-C",
+            "-h",
+            "--user",
+            "--group",
+            "--close-from",
+            "--host",
+        ],
+    ),
+    ("doas", &["-u"]),
+    ("nice", &["-n", "--adjustment"]),
+    ("env", &["-u", "-C", "--unset", "--chdir"]),
+];
+
+/// Returns `true` if `option` of `precommand` takes the next word as its argument.
+fn takes_argument(precommand: &str, option: &str) -> bool {
+    OPTIONS_WITH_ARGUMENT
+        .iter()
+        .any(|(p, options)| *p == precommand && options.contains(&option))
+}
+
+fn is_assignment(word: &str) -> bool {
+    let name_len = word
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(word.len());
+    let (name, rest) = word.split_at(name_len);
+
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && (rest.starts_with('=') || rest.starts_with("+="))
+}
+
+/// Reserved words after which a new command begins.
+static RESERVED_WORDS: &[&str] = &[
+    "!", "{", "}", "if", "then", "elif", "else", "fi", "while", "until", "do", "done", "esac",
+    "coproc",
+];
+
+#[derive(Debug, Eq, PartialEq)]
+enum FrameKind {
+    Top,
+    Subshell,
+    Substitution,
+    Backtick,
+}
+
+#[derive(Debug)]
+struct Frame {
+    kind: FrameKind,
+    open: usize,
+    words: Vec<(usize, usize)>,
+    word_start: Option<usize>,
+    double_quoted: bool,
+}
+
+impl Frame {
+    fn new(kind: FrameKind, open: usize) -> Self {
+        Self {
+            kind,
+            open,
+            words: Vec::new(),
+            word_start: None,
+            double_quoted: false,
+        }
+    }
+
+    fn begin_word(&mut self, i: usize) {
+        self.word_start.get_or_insert(i);
+    }
+
+    fn end_word(&mut self, line: &str, i: usize) {
+        if let Some(start) = self.word_start.take() {
+            self.words.push((start, i));
+
+            if self.words.len() == 1 && RESERVED_WORDS.contains(&&line[start..i]) {
+                self.words.clear();
+            }
+        }
+    }
+
+    fn end_command(&mut self, line: &str, i: usize) {
+        self.end_word(line, i);
+        self.words.clear();
+    }
+}
+
+/// Splits `line` into commands and returns the one that the cursor is placed in.
+///
+/// `cursor` is a byte index of `line`, clamped to its length and rounded down to a character boundary.
+/// The word under the cursor extends to both sides of the cursor.
+/// Quotes, backslash escapes, command substitutions, subshells and reserved words are taken into account.
+pub fn parse(line: &str, cursor: usize) -> Command<'_> {
+    let cursor = line.floor_char_boundary(cursor);
+    let mut stack = vec![Frame::new(FrameKind::Top, 0)];
+    let mut i = 0;
+
+    while i < cursor {
+        i = step(line, &mut stack, i);
+    }
+
+    // Continue until the word under the cursor ends
+    let depth = stack.len();
+    let frame = &stack[depth - 1];
+    let mut words: Vec<_> = frame
+        .words
+        .iter()
+        .map(|&(start, end)| Word {
+            text: &line[start..end],
+            start,
+        })
+        .collect();
+    let mut cursor_word_start = frame.word_start;
+
+    let mut end = i;
+    while end < line.len() {
+        let next = step(line, &mut stack, end);
+        match stack.get(depth - 1).and_then(|frame| frame.word_start) {
+            Some(start) => cursor_word_start = cursor_word_start.or(Some(start)),
+            None => break,
+        }
+        end = next;
+    }
+    let end = end.min(line.len());
+    let cursor_word_start = cursor_word_start.unwrap_or(end);
+
+    words.push(Word {
+        text: &line[cursor_word_start..end],
+        start: cursor_word_start,
+    });
+
+    Command {
+        cursor: words.len() - 1,
+        words,
+    }
+}
+
+/// Returns `(command_start, word_start)` for every word in `line`, including the words in command substitutions.
+///
+/// The word at `word_start` is the word under the cursor of `parse(line, word_start)`, which starts at `command_start`.
+pub fn word_starts(line: &str) -> Vec<(usize, usize)> {
+    let mut stack = vec![Frame::new(FrameKind::Top, 0)];
+    let mut starts = Vec::new();
+    let mut i = 0;
+
+    while i < line.len() {
+        let depth = stack.len();
+        let next = step(line, &mut stack, i);
+        if let Some(frame) = stack.get(depth - 1)
+            && frame.word_start == Some(i)
+        {
+            let command_start = frame.words.first().map_or(i, |&(start, _)| start);
+            starts.push((command_start, i));
+        }
+        i = next;
+    }
+    starts
+}
+
+/// Processes the token at `line[i]` and returns the index of the next one.
+fn step(line: &str, stack: &mut Vec<Frame>, mut i: usize) -> usize {
+    let bytes = line.as_bytes();
+    let frame = stack.last_mut().unwrap();
+    let c = bytes[i];
+    let next = bytes.get(i + 1).copied();
+
+    if frame.double_quoted {
+        match (c, next) {
+            (b'\\', _) => i += 1,
+            (b'"', _) => frame.double_quoted = false,
+            (b'$', Some(b'(')) if bytes.get(i + 2) == Some(&b'(') => {
+                i = skip_balanced(bytes, i + 1) - 1;
+            }
+            (b'$', Some(b'(')) => {
+                stack.push(Frame::new(FrameKind::Substitution, i));
+                i += 1;
+            }
+            (b'$', Some(b'{')) => i = skip_balanced(bytes, i + 1) - 1,
+            (b'`', _) => stack.push(Frame::new(FrameKind::Backtick, i)),
+            _ => {}
+        }
+        return i + 1;
+    }
+
+    match (c, next) {
+        (b' ' | b'\t', _) => frame.end_word(line, i),
+        (b'\\', Some(b'\n')) => {
+            frame.end_word(line, i);
+            i += 1;
+        }
+        (b'\\', _) => {
+            frame.begin_word(i);
+            i += 1;
+        }
+        (b'\'', _) => {
+            frame.begin_word(i);
+            i = line[i + 1..]
+                .find('\'')
+                .map(|j| i + 1 + j)
+                .unwrap_or(bytes.len());
+        }
+        (b'"', _) => {
+            frame.begin_word(i);
+            frame.double_quoted = true;
+        }
+        (b'$', Some(b'(')) if bytes.get(i + 2) == Some(&b'(') => {
+            frame.begin_word(i);
+            i = skip_balanced(bytes, i + 1) - 1;
+        }
+        (b'$', Some(b'(')) => {
+            frame.begin_word(i);
+            stack.push(Frame::new(FrameKind::Substitution, i));
+            i += 1;
+        }
+        (b'$', Some(b'{')) => {
+            frame.begin_word(i);
+            i = skip_balanced(bytes, i + 1) - 1;
+        }
+        (b'`', _) if frame.kind == FrameKind::Backtick => {
+            frame.end_word(line, i);
+            stack.pop();
+        }
+        (b'`', _) => {
+            frame.begin_word(i);
+            stack.push(Frame::new(FrameKind::Backtick, i));
+        }
+        (b'(', Some(b'(')) if frame.word_start.is_none() && frame.words.is_empty() => {
+            // Arithmetic command `(( ... ))`
+            frame.begin_word(i);
+            i = skip_balanced(bytes, i) - 1;
+        }
+        (b'(', _) if frame.word_start.is_none() => {
+            stack.push(Frame::new(FrameKind::Subshell, i));
+        }
+        (b'(', _) => {
+            // Glob qualifiers or alternatives such as `*(.)`
+            i = skip_balanced(bytes, i) - 1;
+        }
+        (b')', _) => {
+            frame.end_word(line, i);
+            match frame.kind {
+                FrameKind::Subshell => {
+                    let open = frame.open;
+                    stack.pop();
+                    let parent = stack.last_mut().unwrap();
+                    parent.words.push((open, i + 1));
+                }
+                FrameKind::Substitution => {
+                    stack.pop();
+                }
+                FrameKind::Top | FrameKind::Backtick => frame.end_command(line, i),
+            }
+        }
+        (b'\n', _) => frame.end_command(line, i),
+        (b';', _) => {
+            frame.end_command(line, i);
+            if matches!(next, Some(b';' | b'&' | b'|')) {
+                i += 1; // `;;`, `;&` and `;|`
+            }
+        }
+        (b'&', _) if i > 0 && matches!(bytes[i - 1], b'>' | b'<') => {
+            frame.begin_word(i); // `>&` and `<&`
+        }
+        (b'&', Some(b'>')) => frame.begin_word(i), // `&>`
+        (b'&', _) => {
+            frame.end_command(line, i);
+            if matches!(next, Some(b'&' | b'|' | b'!')) {
+                i += 1; // `&&`, `&|` and `&!`
+            }
+        }
+        (b'|', _) if i > 0 && bytes[i - 1] == b'>' => frame.begin_word(i), // `>|`
+        (b'|', _) => {
+            frame.end_command(line, i);
+            if matches!(next, Some(b'|' | b'&')) {
+                i += 1; // `||` and `|&`
+            }
+        }
+        _ => frame.begin_word(i),
+    }
+    i + 1
+}
+
+/// Returns the index after the parenthesis or brace that closes `bytes[open]`.
+fn skip_balanced(bytes: &[u8], open: usize) -> usize {
+    let (open_char, close_char) = match bytes[open] {
+        b'(' => (b'(', b')'),
+        _ => (b'{', b'}'),
+    };
+
+    let mut depth = 0;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'\'' => {
+                i += bytes[i + 1..]
+                    .iter()
+                    .position(|&c| c == b'\'')
+                    .map(|j| j + 1)
+                    .unwrap_or(bytes.len());
+            }
+            c if c == open_char => depth += 1,
+            c if c == close_char => {
+                depth -= 1;
+                if depth == 0 {
+                    return i + 1;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        struct Scenario {
+            testname: &'static str,
+            line: &'static str,
+            expected: &'static [&'static str],
+            expected_start: usize,
+        }
+
+        let scenarios = &[
+            Scenario {
+                testname: "empty",
+                line: "",
+                expected: &[""],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "simple",
+                line: "git commit",
+                expected: &["git", "commit"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "leading spaces",
+                line: "  git",
+                expected: &["git"],
+                expected_start: 2,
+            },
+            Scenario {
+                testname: "trailing space",
+                line: "git ",
+                expected: &["git", ""],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "semicolon",
+                line: "echo hello; git commit",
+                expected: &["git", "commit"],
+                expected_start: 12,
+            },
+            Scenario {
+                testname: "and",
+                line: "echo hello && git commit",
+                expected: &["git", "commit"],
+                expected_start: 14,
+            },
+            Scenario {
+                testname: "or",
+                line: "false || git",
+                expected: &["git"],
+                expected_start: 9,
+            },
+            Scenario {
+                testname: "pipeline",
+                line: "seq 10 | tail -3 | cat",
+                expected: &["cat"],
+                expected_start: 19,
+            },
+            Scenario {
+                testname: "background",
+                line: "sleep 1 & g",
+                expected: &["g"],
+                expected_start: 10,
+            },
+            Scenario {
+                testname: "newline",
+                line: "echo\ng",
+                expected: &["g"],
+                expected_start: 5,
+            },
+            Scenario {
+                testname: "single quotes",
+                line: "echo 'a;b' g",
+                expected: &["echo", "'a;b'", "g"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "double quotes",
+                line: r#"echo "x|y" null"#,
+                expected: &["echo", r#""x|y""#, "null"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "escaped quote in double quotes",
+                line: r#"echo "a\";b" c"#,
+                expected: &["echo", r#""a\";b""#, "c"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "backslash",
+                line: r"echo a\;b\ c d",
+                expected: &["echo", r"a\;b\ c", "d"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "unclosed quote",
+                line: "echo 'a; b",
+                expected: &["echo", "'a; b"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "closed command substitution",
+                line: "echo $(date; pwd) x",
+                expected: &["echo", "$(date; pwd)", "x"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "unclosed command substitution",
+                line: "echo $(git c",
+                expected: &["git", "c"],
+                expected_start: 7,
+            },
+            Scenario {
+                testname: "command substitution in double quotes",
+                line: r#"echo "$(git c"#,
+                expected: &["git", "c"],
+                expected_start: 8,
+            },
+            Scenario {
+                testname: "closed command substitution in double quotes",
+                line: r#"echo "$(echo ")")" x"#,
+                expected: &["echo", r#""$(echo ")")""#, "x"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "backticks",
+                line: "echo `date` x",
+                expected: &["echo", "`date`", "x"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "unclosed backticks",
+                line: "echo `git c",
+                expected: &["git", "c"],
+                expected_start: 6,
+            },
+            Scenario {
+                testname: "arithmetic",
+                line: "echo $((1 | 2)) x",
+                expected: &["echo", "$((1 | 2))", "x"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "parameter expansion",
+                line: "echo ${a:-;} x",
+                expected: &["echo", "${a:-;}", "x"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "subshell",
+                line: "(cd /tmp; g",
+                expected: &["g"],
+                expected_start: 10,
+            },
+            Scenario {
+                testname: "closed subshell",
+                line: "(cd /tmp; ls) >/dev/null",
+                expected: &["(cd /tmp; ls)", ">/dev/null"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "glob qualifiers",
+                line: "ls *(.) x",
+                expected: &["ls", "*(.)", "x"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "current shell",
+                line: "{ echo; g",
+                expected: &["g"],
+                expected_start: 8,
+            },
+            Scenario {
+                testname: "if then",
+                line: "if true; then g",
+                expected: &["g"],
+                expected_start: 14,
+            },
+            Scenario {
+                testname: "while do",
+                line: "while read -r l; do echo",
+                expected: &["echo"],
+                expected_start: 20,
+            },
+            Scenario {
+                testname: "reserved word in argument",
+                line: "echo if then",
+                expected: &["echo", "if", "then"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "redirections",
+                line: "echo a 2>&1 >| b &> c g",
+                expected: &["echo", "a", "2>&1", ">|", "b", "&>", "c", "g"],
+                expected_start: 0,
+            },
+            Scenario {
+                testname: "case",
+                line: "case $a in x) g",
+                expected: &["g"],
+                expected_start: 14,
+            },
+        ];
+
+        for s in scenarios {
+            let actual = parse(s.line, s.line.len());
+            let words: Vec<_> = actual.words.iter().map(|w| w.text).collect();
+
+            assert_eq!(words, s.expected, "{}", s.testname);
+            assert_eq!(actual.cursor, s.expected.len() - 1, "{}", s.testname);
+            assert_eq!(actual.words[0].start, s.expected_start, "{}", s.testname);
+            for w in &actual.words {
+                assert_eq!(&s.line[w.start..w.end()], w.text, "{}", s.testname);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_with_cursor() {
+        let scenarios: &[(&str, usize, &[&str])] = &[
+            ("git commit", 10, &["git", "commit"]),
+            ("git commit", 7, &["git", "commit"]),
+            ("git commit", 4, &["git", "commit"]),
+            ("git commit", 3, &["git"]),
+            ("git  commit", 4, &["git", ""]),
+            ("git commit", 0, &["git"]),
+            ("git c; ls", 5, &["git", "c"]),
+            ("git c|ls", 4, &["git", "c"]),
+            ("echo 'a b' c", 7, &["echo", "'a b'"]),
+            ("echo $(git c) d", 11, &["git", "c"]),
+            ("echo \"$(git c)\" d", 12, &["git", "c"]),
+            ("echo a$(pwd)b c", 6, &["echo", "a$(pwd)b"]),
+            ("echo `git c` d", 10, &["git", "c"]),
+        ];
+
+        for &(line, cursor, expected) in scenarios {
+            let actual = parse(line, cursor);
+            let words: Vec<_> = actual.words.iter().map(|w| w.text).collect();
+
+            assert_eq!(words, expected, "{line:?} at {cursor}");
+            assert_eq!(actual.cursor, expected.len() - 1, "{line:?} at {cursor}");
+            for w in &actual.words {
+                assert_eq!(&line[w.start..w.end()], w.text, "{line:?} at {cursor}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_word_starts() {
+        assert_eq!(
+            word_starts("echo a; git c"),
+            [(0, 0), (0, 5), (8, 8), (8, 12)]
+        );
+
+        // Same as parsing the line at each position
+        let lines = [
+            "",
+            "git commit -m 'a b'",
+            "  echo hello; git c && FOO=1 sudo g",
+            "echo \"$(git rev-parse) `g`\" (cd ..; g)",
+            "if true; then g; fi",
+            "echo ${a} $((1 + 2)) >&2 a\\ b",
+            "echo \"unterminated 'quote",
+            "echo こんにちは g",
+        ];
+
+        for line in lines {
+            let expected: Vec<_> = line
+                .char_indices()
+                .map(|(i, _)| (i, parse(line, i)))
+                .filter(|(i, parsed)| {
+                    let word = parsed.cursor_word();
+                    word.start == *i && !word.text.is_empty()
+                })
+                .map(|(i, parsed)| (parsed.words[0].start, i))
+                .collect();
+            assert_eq!(word_starts(line), expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn test_command_index() {
+        let precommands = ["sudo", "doas", "env", "nohup", "time", "nice"];
+
+        let scenarios: &[(&str, usize)] = &[
+            ("g", 0),
+            ("git c", 0),
+            ("sudo g", 1),
+            ("sudo", 0),
+            ("sudo ", 1),
+            ("sudo -E g", 2),
+            ("FOO=1 g", 1),
+            ("FOO=1 BAR+=2 g", 2),
+            ("FOO=1 sudo env -i BAR=2 g", 5),
+            ("time nohup g", 2),
+            ("echo sudo g", 0),
+            ("-E g", 0),
+            ("1FOO=1 g", 0),
+            ("=1 g", 0),
+            ("echo; sudo g", 1),
+            ("sudo -u root g", 3),
+            ("sudo -uroot g", 2),
+            ("sudo -E -g wheel -C 3 -h host g", 8),
+            ("sudo --user root g", 3),
+            ("sudo --user=root g", 2),
+            ("doas -u root g", 3),
+            ("nice -n 10 g", 3),
+            ("env -u FOO -C /tmp BAR=1 g", 6),
+            ("nohup -u root g", 2),
+        ];
+
+        for &(line, expected) in scenarios {
+            assert_eq!(
+                parse(line, line.len()).command_index(&precommands),
+                expected,
+                "{line}"
+            );
+        }
+    }
+}
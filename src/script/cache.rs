@@ -0,0 +1,208 @@
+use shell_escape::escape;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use zabrze::config::snippet::Cache;
+use zabrze::expand::Expansion;
+
+/// Session caches unused for this long are removed, as their shells have most likely exited.
+const SESSION_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Returns the path to the cache file of the evaluated expansion.
+///
+/// The key consists of the snippet, the trigger, the captures and the working directory.
+/// Session caches are additionally keyed by the session ID, and are not available without one.
+pub fn entry_path(
+    cache_dir: &Path,
+    session: Option<&str>,
+    cwd: &Path,
+    trigger: &str,
+    expansion: &Expansion,
+    cache: Cache,
+) -> Option<PathBuf> {
+    let mut hasher = Fnv1a::new();
+    hasher.write(expansion.left_snippet.as_bytes());
+    hasher.write(expansion.right_snippet.as_bytes());
+    hasher.write(trigger.as_bytes());
+    for var in &expansion.variables {
+        hasher.write(var.name.as_bytes());
+        hasher.write(var.value.as_bytes());
+    }
+    hasher.write(cwd.as_os_str().as_encoded_bytes());
+
+    let filename = match cache {
+        Cache::Session => {
+            let session = session.filter(|s| s.chars().all(|c| c.is_ascii_alphanumeric()))?;
+            format!("session-{}-{:016x}", session, hasher.finish())
+        }
+        Cache::Ttl(_) => format!("ttl-{:016x}", hasher.finish()),
+    };
+    Some(cache_dir.join(filename))
+}
+
+/// Returns the cached `(left_snippet, right_snippet)` if it has not expired yet.
+pub fn load(path: &Path, cache: Cache) -> Option<(String, String)> {
+    if let Cache::Ttl(ttl) = cache {
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+        let elapsed = SystemTime::now().duration_since(modified).ok()?;
+        if elapsed >= ttl {
+            return None;
+        }
+    }
+
+    let content = fs::read_to_string(path).ok()?;
+    let entry = content
+        .split_once('\0')
+        .map(|(left, right)| (left.to_string(), right.to_string()))?;
+
+    if cache == Cache::Session {
+        // Keeps the entry from being pruned while the session uses it
+        let _ = File::options()
+            .append(true)
+            .open(path)
+            .and_then(|f| f.set_modified(SystemTime::now()));
+    }
+    Some(entry)
+}
+
+/// Removes the session caches in `cache_dir` that have not been used for [`SESSION_EXPIRY`].
+pub fn prune_sessions(cache_dir: &Path) {
+    let Ok(entries) = fs::read_dir(cache_dir) else {
+        return;
+    };
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        if !entry.file_name().to_string_lossy().starts_with("session-") {
+            continue;
+        }
+        let is_stale = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| now.duration_since(modified).ok())
+            .is_some_and(|elapsed| elapsed >= SESSION_EXPIRY);
+        if is_stale {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Returns shell code that writes the evaluated `$left_snippet` and `$right_snippet` to `path`.
+pub fn store_script(path: &Path) -> String {
+    let path = escape(path.to_string_lossy());
+    format!(r#"printf '%s\0%s' "${{left_snippet}}" "${{right_snippet}}" 2>/dev/null >|{path};"#)
+}
+
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes.iter().chain(&[0]) {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+    use zabrze::expand::ExpansionVariable;
+
+    fn expansion(value: &str) -> Expansion<'_> {
+        Expansion {
+            replacing_range: 0..0,
+            left_snippet: "$(git symbolic-ref --short HEAD)".into(),
+            right_snippet: "".into(),
+            condition: None,
+            variables: vec![ExpansionVariable {
+                name: "n".to_string(),
+                value,
+            }],
+            evaluate: true,
+            has_placeholder: false,
+            abort_on_error: false,
+            quote_captures: false,
+            cache: Some(Cache::Session),
+            name: "git branch",
+            index: 0,
+            file: None,
+        }
+    }
+
+    #[test]
+    fn test_entry_path() {
+        let dir = Path::new("/cache");
+        let ttl = Cache::Ttl(Duration::from_secs(30));
+        let path = |session, cwd: &str, trigger, value, cache| {
+            entry_path(
+                dir,
+                session,
+                Path::new(cwd),
+                trigger,
+                &expansion(value),
+                cache,
+            )
+        };
+
+        let base = path(None, "/a", "B", "1", ttl).unwrap();
+        assert!(base.starts_with(dir));
+        assert!(base.to_string_lossy().starts_with("/cache/ttl-"));
+
+        assert_eq!(path(Some("42"), "/a", "B", "1", ttl), Some(base.clone()));
+        assert_ne!(path(None, "/b", "B", "1", ttl), Some(base.clone()));
+        assert_ne!(path(None, "/a", "C", "1", ttl), Some(base.clone()));
+        assert_ne!(path(None, "/a", "B", "2", ttl), Some(base.clone()));
+
+        assert_eq!(path(None, "/a", "B", "1", Cache::Session), None);
+        let session = path(Some("42"), "/a", "B", "1", Cache::Session).unwrap();
+        assert!(session.to_string_lossy().starts_with("/cache/session-42-"));
+        assert_eq!(path(Some("../42"), "/a", "B", "1", Cache::Session), None);
+    }
+
+    #[test]
+    fn test_prune_sessions() {
+        let dir = env::temp_dir().join(format!("zabrze-test-prune-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let write = |name: &str, age: Duration| {
+            let path = dir.join(name);
+            fs::write(&path, "a\0").unwrap();
+            let modified = SystemTime::now() - age;
+            File::options()
+                .append(true)
+                .open(&path)
+                .and_then(|f| f.set_modified(modified))
+                .unwrap();
+            path
+        };
+        let stale = write("session-1-0", SESSION_EXPIRY);
+        let used = write("session-2-0", SESSION_EXPIRY);
+        let fresh = write("session-3-0", Duration::ZERO);
+        let ttl = write("ttl-0", SESSION_EXPIRY);
+
+        // Loading an entry marks it as used
+        assert_eq!(
+            load(&used, Cache::Session),
+            Some(("a".to_string(), String::new()))
+        );
+
+        prune_sessions(&dir);
+        assert!(!stale.exists());
+        assert!(used.exists());
+        assert!(fresh.exists());
+        assert!(ttl.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
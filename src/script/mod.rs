@@ -0,0 +1,320 @@
+mod cache;
+
+use crate::opt::{ErrorOutput, ExpandArgs, ExpandLineArgs, Format, Shell};
+use crate::stats;
+use shell_escape::escape;
+use std::borrow::Cow;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::time::Instant;
+use zabrze::config;
+use zabrze::config::snippet::Cache;
+use zabrze::expand;
+
+/// Saves the buffers before expansion for `__zabrze::revert`.
+const SAVE_BUFFERS: &str =
+    r#"__zabrze_revert_lbuffer="$LBUFFER";__zabrze_revert_rbuffer="$RBUFFER";"#;
+
+/// Returns the expression of the variable `name`, evaluated if `evaluate` is `true`.
+///
+/// bash has no `(e)` flag, so `__zabrze_eval` defined by the bash init script emulates it.
+fn evaluated(shell: Shell, name: &str, evaluate: bool) -> String {
+    match (shell, evaluate) {
+        (Shell::Zsh, true) => format!(r#""${{(e){name}}}""#),
+        (Shell::Bash, true) => format!(r#""$(__zabrze_eval "${{{name}}}")""#),
+        (_, false) => format!(r#""${{{name}}}""#),
+    }
+}
+
+/// Returns the code that replaces the buffer with `{prefix}{left}{lbuffer_tail}|{right}{rbuffer}`, where `|` is the cursor.
+fn assign_buffers(
+    shell: Shell,
+    prefix: &str,
+    left: &str,
+    lbuffer_tail: &str,
+    right: &str,
+    rbuffer: &str,
+) -> String {
+    match shell {
+        Shell::Zsh => format!(
+            r#"{SAVE_BUFFERS}LBUFFER={prefix}{left}{lbuffer_tail};RBUFFER={right}{rbuffer};"#
+        ),
+        // $READLINE_POINT is an index of characters as well as ${#var}
+        Shell::Bash => format!(
+            r#"local __zabrze_lbuffer={prefix}{left}{lbuffer_tail};READLINE_LINE="${{__zabrze_lbuffer}}"{right}{rbuffer};READLINE_POINT="${{#__zabrze_lbuffer}}";"#
+        ),
+    }
+}
+
+pub fn run(args: &ExpandArgs) {
+    let start = Instant::now();
+    let (config, mut errors) = crate::load_config();
+
+    let (buffer, cursor) = match (&args.buffer, args.cursor) {
+        (Some(buffer), Some(cursor)) => {
+            // $CURSOR and $READLINE_POINT are indices of characters
+            let cursor = buffer
+                .char_indices()
+                .nth(cursor)
+                .map(|(i, _)| i)
+                .unwrap_or(buffer.len());
+            (buffer.clone(), cursor)
+        }
+        _ => {
+            let lbuffer = args.lbuffer.as_deref().unwrap_or_default();
+            let rbuffer = args.rbuffer.as_deref().unwrap_or_default();
+            (format!("{lbuffer}{rbuffer}"), lbuffer.len())
+        }
+    };
+
+    let mut result = expand::expand(&config, &buffer, cursor, args.key.map(Into::into));
+    errors.extend(result.errors.iter().map(ToString::to_string));
+
+    if args.format == Format::Json {
+        crate::print_errors(&errors);
+        // Ranges are in characters as well as --cursor
+        let char_index = |i: usize| buffer[..i].chars().count();
+        for expansion in &mut result.expansions {
+            let range = &expansion.replacing_range;
+            expansion.replacing_range = char_index(range.start)..char_index(range.end);
+        }
+        println!("{}", serde_json::to_string(&result).unwrap());
+        return;
+    }
+
+    let error_script = match args.error_output {
+        ErrorOutput::Stderr => {
+            crate::print_errors(&errors);
+            String::new()
+        }
+        ErrorOutput::Variable => crate::error_script(&errors),
+    };
+
+    if result.expansions.is_empty() {
+        log::debug!("no expansion in {:?}", start.elapsed());
+        if !error_script.is_empty() {
+            println!("{error_script}");
+        }
+        return;
+    }
+
+    let mut script = error_script;
+
+    let command = escape(Cow::from(result.command));
+    let trigger = escape(Cow::from(result.last_arg));
+
+    write!(
+        script,
+        r#"local command={command} trigger={trigger} abbr={trigger};"#
+    )
+    .unwrap(); // `abbr` is deprecated, remain for backward compatibility
+
+    let cache_dir = config::get_default_cache_dir().map(PathBuf::from);
+    let cwd = env::current_dir().unwrap_or_default();
+    let log_path = stats::log_path()
+        .filter(|_| config.stats)
+        .filter(|path| path.parent().is_some_and(|d| fs::create_dir_all(d).is_ok()));
+    let timestamp = stats::now();
+
+    let mut has_if = false;
+    for expansion in &result.expansions {
+        let cache_path = match (expansion.cache, &cache_dir) {
+            (Some(cache), Some(cache_dir)) => cache::entry_path(
+                cache_dir,
+                args.session.as_deref(),
+                &cwd,
+                result.last_arg,
+                expansion,
+                cache,
+            )
+            .map(|path| (path, cache)),
+            _ => None,
+        };
+        let cached = cache_path
+            .as_ref()
+            .and_then(|(path, cache)| cache::load(path, *cache));
+        let store_path = match (&cache_path, &cached) {
+            (Some((path, _)), None)
+                if path.parent().is_some_and(|d| fs::create_dir_all(d).is_ok()) =>
+            {
+                Some(path)
+            }
+            _ => None,
+        };
+        if let (Some(_), Some((_, Cache::Session)), Some(cache_dir)) =
+            (store_path, &cache_path, &cache_dir)
+        {
+            // Shells do not remove their session caches on exit
+            cache::prune_sessions(cache_dir);
+        }
+
+        let (prefix, lbuffer_tail, rbuffer) = expansion.split_buffer(&buffer, cursor);
+        let prefix = escape(Cow::from(prefix));
+        let lbuffer_tail = if lbuffer_tail.is_empty() {
+            Cow::from("")
+        } else {
+            escape(Cow::from(lbuffer_tail))
+        };
+        let rbuffer = escape(Cow::from(rbuffer));
+        let (left_snippet, right_snippet, evaluate) = match cached {
+            Some((left, right)) => (escape(Cow::from(left)), escape(Cow::from(right)), false),
+            None => (
+                escape(expansion.left_snippet.clone()),
+                escape(expansion.right_snippet.clone()),
+                expansion.evaluate,
+            ),
+        };
+        let condition = expansion.condition.map(|c| escape(Cow::from(c)));
+
+        let record = match &log_path {
+            Some(path) => {
+                let file = expansion
+                    .file
+                    .map(|f| f.to_string_lossy())
+                    .unwrap_or_default();
+                let entry = stats::Entry {
+                    timestamp,
+                    file: &file,
+                    name: expansion.name,
+                    trigger: result.last_arg,
+                };
+                stats::record_script(args.shell, path, &entry)
+            }
+            None => String::new(),
+        };
+
+        let name = escape(Cow::from(expansion.name));
+        let has_placeholder = if expansion.has_placeholder { "1" } else { "" };
+
+        if let Some(condition) = &condition {
+            if !has_if {
+                write!(script, r#"if eval {condition};then "#).unwrap();
+            } else {
+                write!(script, r#"elif eval {condition};then "#).unwrap();
+            }
+            has_if = true;
+        } else if has_if {
+            write!(script, r"else ").unwrap();
+        }
+
+        if expansion.quote_captures {
+            let trigger = escape(escape(Cow::from(result.last_arg)));
+            write!(script, r#"local trigger={trigger} abbr={trigger};"#).unwrap();
+        }
+
+        for var in expansion.variables.iter() {
+            let name = escape(Cow::from(&var.name));
+            let value = if expansion.quote_captures {
+                escape(escape(Cow::from(var.value)))
+            } else {
+                escape(Cow::from(var.value))
+            };
+            write!(script, r#"local {name}={value};"#).unwrap();
+        }
+
+        let plain_left = evaluated(args.shell, "left_snippet", false);
+        let plain_right = evaluated(args.shell, "right_snippet", false);
+        let evaluated_left = evaluated(args.shell, "left_snippet", evaluate);
+        let evaluated_right = evaluated(args.shell, "right_snippet", evaluate);
+
+        write!(
+            script,
+            r"local left_snippet={left_snippet} right_snippet={right_snippet};"
+        )
+        .unwrap();
+        if expansion.abort_on_error {
+            write!(script, r#"if left_snippet={evaluated_left} && "#).unwrap();
+            write!(script, r#"right_snippet={evaluated_right};then "#).unwrap();
+            write!(
+                script,
+                "{}",
+                assign_buffers(
+                    args.shell,
+                    &prefix,
+                    &plain_left,
+                    &lbuffer_tail,
+                    &plain_right,
+                    &rbuffer
+                )
+            )
+            .unwrap();
+            write!(
+                script,
+                r"__zabrze_has_placeholder={has_placeholder};__zabrze_snippet={name};{record}"
+            )
+            .unwrap();
+            if let Some(path) = store_path {
+                write!(script, "{}", cache::store_script(path)).unwrap();
+            }
+            write!(script, r#"fi"#).unwrap();
+        } else if let Some(path) = store_path {
+            write!(script, r#"left_snippet={evaluated_left};"#).unwrap();
+            write!(script, r#"right_snippet={evaluated_right};"#).unwrap();
+            write!(
+                script,
+                "{}",
+                assign_buffers(
+                    args.shell,
+                    &prefix,
+                    &plain_left,
+                    &lbuffer_tail,
+                    &plain_right,
+                    &rbuffer
+                )
+            )
+            .unwrap();
+            write!(
+                script,
+                r"__zabrze_has_placeholder={has_placeholder};__zabrze_snippet={name};{record}"
+            )
+            .unwrap();
+            write!(script, "{}", cache::store_script(path)).unwrap();
+        } else {
+            write!(
+                script,
+                "{}",
+                assign_buffers(
+                    args.shell,
+                    &prefix,
+                    &evaluated_left,
+                    &lbuffer_tail,
+                    &evaluated_right,
+                    &rbuffer
+                )
+            )
+            .unwrap();
+            write!(
+                script,
+                r"__zabrze_has_placeholder={has_placeholder};__zabrze_snippet={name};{record}"
+            )
+            .unwrap();
+        }
+
+        if condition.is_none() {
+            break;
+        }
+    }
+
+    if has_if {
+        write!(script, r"fi").unwrap();
+    }
+
+    log::debug!("generated code in {:?}: {script}", start.elapsed());
+    println!("{script}");
+}
+
+pub fn run_line(args: &ExpandLineArgs) {
+    let (config, mut errors) = crate::load_config();
+
+    match expand::expand_line(&config, &args.line) {
+        Ok(line) => crate::print_output(args.error_output, Some(&format!("{line}\n")), &errors),
+        Err(error) => {
+            errors.push(error.to_string());
+            crate::print_output(args.error_output, None, &errors);
+            process::exit(1);
+        }
+    }
+}
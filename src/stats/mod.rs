@@ -0,0 +1,237 @@
+use crate::opt::{Shell, StatsArgs};
+use shell_escape::escape;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zabrze::config::{self, Config};
+
+static LOG_FILENAME: &str = "usage.log";
+
+/// An entry of the usage log, one line of `timestamp\tfile\tname\ttrigger`.
+///
+/// The buffer is never recorded.
+#[derive(Debug, Eq, PartialEq)]
+pub struct Entry<'a> {
+    pub timestamp: u64,
+    pub file: &'a str,
+    pub name: &'a str,
+    pub trigger: &'a str,
+}
+
+impl Entry<'_> {
+    fn parse(line: &str) -> Option<Entry<'_>> {
+        let mut fields = line.splitn(4, '\t');
+        Some(Entry {
+            timestamp: fields.next()?.parse().ok()?,
+            file: fields.next()?,
+            name: fields.next()?,
+            trigger: fields.next()?,
+        })
+    }
+
+    fn format(&self) -> String {
+        let sanitize = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+        format!(
+            "{}\t{}\t{}\t{}",
+            self.timestamp,
+            sanitize(self.file),
+            sanitize(self.name),
+            sanitize(self.trigger),
+        )
+    }
+}
+
+pub fn log_path() -> Option<PathBuf> {
+    config::get_default_state_dir().map(|dir| Path::new(&dir).join(LOG_FILENAME))
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Returns shell code that appends `entry` to the usage log at `path`.
+pub fn record_script(shell: Shell, path: &Path, entry: &Entry) -> String {
+    let line = escape(Cow::from(entry.format()));
+    let path = escape(path.to_string_lossy());
+    match shell {
+        Shell::Zsh => format!(r#"print -r -- {line} 2>/dev/null >>|{path};"#),
+        Shell::Bash => format!(r#"printf '%s\n' {line} 2>/dev/null >>{path};"#),
+    }
+}
+
+pub fn run(args: &StatsArgs) {
+    let config = crate::load_config_or_warn();
+    let log = log_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .unwrap_or_default();
+
+    stats(args, &config, &log, now(), &mut io::stdout()).unwrap();
+}
+
+fn stats<W: io::Write>(
+    args: &StatsArgs,
+    config: &Config,
+    log: &str,
+    now: u64,
+    out: &mut W,
+) -> Result<(), io::Error> {
+    let entries: Vec<_> = log.lines().filter_map(Entry::parse).collect();
+
+    let mut triggers: HashMap<&str, usize> = HashMap::new();
+    for entry in &entries {
+        *triggers.entry(entry.trigger).or_default() += 1;
+    }
+    let mut triggers: Vec<_> = triggers.into_iter().collect();
+    triggers.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+
+    writeln!(out, "Most used triggers:")?;
+    for (trigger, count) in triggers.iter().take(args.top) {
+        writeln!(out, "{count:>8}  {trigger}")?;
+    }
+
+    let since = now.saturating_sub(args.days.saturating_mul(24 * 60 * 60));
+    let used: HashSet<_> = entries
+        .iter()
+        .filter(|e| e.timestamp >= since)
+        .map(|e| (e.file, e.name))
+        .collect();
+
+    writeln!(out)?;
+    writeln!(out, "Snippets unused in the last {} days:", args.days)?;
+    for snippet in &config.snippets {
+        let file = snippet_file(snippet.file.as_deref());
+        if !used.contains(&(file.as_ref(), snippet.display_name())) {
+            writeln!(out, "  {}  ({})", snippet.display_name(), file)?;
+        }
+    }
+
+    let mut files: Vec<(Cow<str>, usize, usize)> = Vec::new();
+    for snippet in &config.snippets {
+        let file = snippet_file(snippet.file.as_deref());
+        match files.iter_mut().find(|(f, _, _)| *f == file) {
+            Some((_, snippets, _)) => *snippets += 1,
+            None => files.push((file, 1, 0)),
+        }
+    }
+    for entry in &entries {
+        match files.iter_mut().find(|(f, _, _)| f == entry.file) {
+            Some((_, _, expansions)) => *expansions += 1,
+            None => files.push((Cow::from(entry.file), 0, 1)),
+        }
+    }
+
+    writeln!(out)?;
+    writeln!(out, "Expansions per file:")?;
+    for (file, snippets, expansions) in &files {
+        writeln!(out, "{expansions:>8}  {file} ({snippets} snippets)")?;
+    }
+
+    Ok(())
+}
+
+fn snippet_file(file: Option<&Path>) -> Cow<'_, str> {
+    file.map(|f| f.to_string_lossy()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry() {
+        let entry = Entry {
+            timestamp: 1700000000,
+            file: "/config.toml",
+            name: "git\tcommit",
+            trigger: "g",
+        };
+        let line = entry.format();
+        assert_eq!(line, "1700000000\t/config.toml\tgit commit\tg");
+        assert_eq!(
+            Entry::parse(&line),
+            Some(Entry {
+                name: "git commit",
+                ..entry
+            })
+        );
+        assert_eq!(Entry::parse("broken"), None);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut config = Config::load_from_str(
+            r#"
+            [[snippets]]
+            name = "git"
+            trigger = "g"
+            snippet = "git"
+
+            [[snippets]]
+            trigger = "null"
+            snippet = ">/dev/null"
+            global = true
+
+            [[snippets]]
+            name = "git commit"
+            trigger = "c"
+            snippet = "commit"
+            global = true
+            context = '^git '
+            "#,
+        )
+        .unwrap();
+        config.snippets[0].file = Some(PathBuf::from("/a.toml"));
+        config.snippets[1].file = Some(PathBuf::from("/a.toml"));
+        config.snippets[2].file = Some(PathBuf::from("/b.toml"));
+
+        let day = 24 * 60 * 60;
+        let now = 100 * day;
+        let log = [
+            format!("{}\t/a.toml\tgit\tg", now - day),
+            format!("{}\t/a.toml\tgit\tg", now - 2 * day),
+            format!("{}\t/a.toml\t>/dev/null\tnull", now - 60 * day),
+            format!("{}\t/b.toml\tgit commit\tc", now - 3 * day),
+            format!("{}\t/old.toml\tremoved\tr", now - 3 * day),
+        ]
+        .join("\n");
+
+        let args = StatsArgs { days: 30, top: 2 };
+        let mut out = Vec::new();
+        stats(&args, &config, &log, now, &mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"Most used triggers:
+       2  g
+       1  c
+
+Snippets unused in the last 30 days:
+  >/dev/null  (/a.toml)
+
+Expansions per file:
+       3  /a.toml (2 snippets)
+       1  /b.toml (1 snippets)
+       1  /old.toml (0 snippets)
+"#
+        );
+
+        // Every entry is within the range instead of overflowing
+        let args = StatsArgs {
+            days: u64::MAX,
+            top: 2,
+        };
+        let mut out = Vec::new();
+        stats(&args, &config, &log, now, &mut out).unwrap();
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains(&format!("in the last {} days:\n\n", u64::MAX))
+        );
+    }
+}
@@ -0,0 +1,338 @@
+use crate::opt::SuggestArgs;
+use ansi_term::Color;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use zabrze::config::{Config, Trigger};
+
+/// zsh stores some bytes in the history file as `META` followed by the byte xor 32.
+const META: u8 = 0x83;
+
+/// Characters that end the plain words of a command line, such as quotes, redirections and pipes.
+const SPECIAL_CHARS: &[char] = &[
+    '|', '&', ';', '<', '>', '(', ')', '{', '}', '$', '`', '\'', '"', '\\', '*', '?', '[', '~',
+];
+
+/// Minimum number of characters saved by a suggested trigger.
+const MIN_SAVING: usize = 4;
+
+#[derive(Debug, Eq, PartialEq)]
+struct Suggestion {
+    trigger: String,
+    snippet: String,
+    count: usize,
+}
+
+pub fn run(args: &SuggestArgs) {
+    let config = crate::load_config_or_warn();
+
+    let history = match fs::read(&args.history) {
+        Ok(history) => unmetafy(&history),
+        Err(error) => {
+            let error_message = format!(
+                "failed to read history '{}': {}",
+                args.history.display(),
+                error
+            );
+            eprintln!("{}", Color::Red.normal().paint(error_message));
+            process::exit(1);
+        }
+    };
+
+    let path_dirs: Vec<_> = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect())
+        .unwrap_or_default();
+
+    let suggestions = suggest(args, &config, &history, &path_dirs);
+    print(&suggestions, &mut io::stdout()).unwrap();
+}
+
+fn unmetafy(bytes: &[u8]) -> String {
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut iter = bytes.iter();
+    while let Some(&b) = iter.next() {
+        if b == META {
+            if let Some(&next) = iter.next() {
+                result.push(next ^ 32);
+            }
+        } else {
+            result.push(b);
+        }
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+/// Parses plain and `EXTENDED_HISTORY` (`: <start>:<elapsed>;<command>`) entries.
+fn parse_history(history: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut current: Option<String> = None;
+
+    for line in history.lines() {
+        let line = match current.take() {
+            Some(mut command) => {
+                command.push('\n');
+                command.push_str(line);
+                command
+            }
+            None => strip_extended_header(line).to_string(),
+        };
+
+        // Multi-line commands are stored with a backslash at the end of each line
+        match line.strip_suffix('\\') {
+            Some(continued) => current = Some(continued.to_string()),
+            None => commands.push(line),
+        }
+    }
+    commands.extend(current);
+
+    commands
+}
+
+fn strip_extended_header(line: &str) -> &str {
+    let Some(rest) = line.strip_prefix(": ") else {
+        return line;
+    };
+    let Some((header, command)) = rest.split_once(';') else {
+        return line;
+    };
+    let is_header = header
+        .split_once(':')
+        .is_some_and(|(start, elapsed)| is_number(start) && is_number(elapsed));
+
+    if is_header { command } else { line }
+}
+
+fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Returns the leading words of `command` that contain no special characters.
+fn plain_words(command: &str) -> Vec<&str> {
+    command
+        .split_whitespace()
+        .take_while(|w| !w.contains(SPECIAL_CHARS))
+        .collect()
+}
+
+fn suggest(
+    args: &SuggestArgs,
+    config: &Config,
+    history: &str,
+    path_dirs: &[PathBuf],
+) -> Vec<Suggestion> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for command in parse_history(history) {
+        let words = plain_words(&command);
+        for n in 1..=words.len() {
+            *counts.entry(words[..n].join(" ")).or_default() += 1;
+        }
+    }
+
+    let existing_snippets: HashSet<_> = config.snippets.iter().map(|s| &s.snippet).collect();
+    let mut candidates: Vec<_> = counts
+        .into_iter()
+        .filter(|(snippet, count)| {
+            *count >= args.min_count
+                && snippet.len() > MIN_SAVING
+                && !existing_snippets.contains(snippet)
+        })
+        .collect();
+
+    // Prefer the candidates that save the most keystrokes
+    candidates.sort_by(|(a, a_count), (b, b_count)| {
+        (b_count * b.len())
+            .cmp(&(a_count * a.len()))
+            .then_with(|| a.cmp(b))
+    });
+
+    let mut used_triggers: HashSet<String> = config
+        .snippets
+        .iter()
+        .filter_map(|s| match &s.trigger {
+            Trigger::Text(trigger) => Some(trigger.clone()),
+            Trigger::Regex(_) => None,
+        })
+        .collect();
+
+    let mut suggestions: Vec<Suggestion> = Vec::new();
+    for (snippet, count) in candidates {
+        if suggestions.len() >= args.limit {
+            break;
+        }
+
+        // A prefix typed exactly as often as its suggested extension (or vice versa) adds nothing
+        let is_redundant = suggestions.iter().any(|s| {
+            s.count == count
+                && (is_word_prefix(&snippet, &s.snippet) || is_word_prefix(&s.snippet, &snippet))
+        });
+        if is_redundant {
+            continue;
+        }
+
+        let trigger = trigger_candidates(&snippet).into_iter().find(|t| {
+            snippet.len() >= t.len() + MIN_SAVING
+                && !used_triggers.contains(t)
+                && !is_executable(t, path_dirs)
+        });
+
+        if let Some(trigger) = trigger {
+            used_triggers.insert(trigger.clone());
+            suggestions.push(Suggestion {
+                trigger,
+                snippet,
+                count,
+            });
+        }
+    }
+
+    suggestions
+}
+
+/// Returns triggers for `snippet` in order of preference, starting with the initials of its words.
+fn trigger_candidates(snippet: &str) -> Vec<String> {
+    let words: Vec<_> = snippet
+        .split_whitespace()
+        .map(|w| w.trim_start_matches('-'))
+        .filter(|w| w.chars().next().is_some_and(|c| c.is_ascii_alphanumeric()))
+        .collect();
+    let Some(last) = words.last() else {
+        return Vec::new();
+    };
+
+    let initials: String = words
+        .iter()
+        .filter_map(|w| w.chars().next())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    let mut candidates = vec![initials.clone()];
+
+    let mut longer = initials.clone();
+    for c in last.chars().skip(1).filter(|c| c.is_ascii_alphanumeric()) {
+        longer.push(c.to_ascii_lowercase());
+        candidates.push(longer.clone());
+    }
+
+    candidates.extend((2..=9).map(|n| format!("{initials}{n}")));
+    candidates
+}
+
+fn is_word_prefix(prefix: &str, s: &str) -> bool {
+    s.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(' '))
+}
+
+fn is_executable(name: &str, path_dirs: &[PathBuf]) -> bool {
+    path_dirs.iter().any(|dir| dir.join(name).is_file())
+}
+
+fn print<W: io::Write>(suggestions: &[Suggestion], out: &mut W) -> Result<(), io::Error> {
+    for (i, s) in suggestions.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+
+        let snippet = toml::Value::String(s.snippet.clone());
+        let trigger = toml::Value::String(s.trigger.clone());
+
+        writeln!(out, "# used {} times", s.count)?;
+        writeln!(out, "[[snippets]]")?;
+        writeln!(out, "name = {snippet}")?;
+        writeln!(out, "trigger = {trigger}")?;
+        writeln!(out, "snippet = {snippet}")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_history() {
+        let history = unmetafy(
+            b": 1700000000:0;git status\n\
+              ls -la\n\
+              : 1700000001:3;echo a\\\n\
+              b\n\
+              : not a header\n\
+              echo \xe3\x81\x83\xa2\n",
+        );
+
+        assert_eq!(
+            parse_history(&history),
+            vec![
+                "git status",
+                "ls -la",
+                "echo a\nb",
+                ": not a header",
+                "echo あ",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_suggest() {
+        let config = Config::load_from_str(
+            r#"
+            [[snippets]]
+            name = "git"
+            trigger = "g"
+            snippet = "git"
+
+            [[snippets]]
+            trigger = "gcm"
+            snippet = "git commit -m '{}'"
+            "#,
+        )
+        .unwrap();
+
+        let history = [
+            "git commit -m 'a'",
+            "git commit -m 'b'",
+            "git commit --amend",
+            "git checkout main",
+            "git checkout main",
+            "git checkout main",
+            "docker compose up -d",
+            "docker compose up -d",
+            "docker compose up -d",
+            "ls",
+            "ls",
+            "ls",
+        ]
+        .join("\n");
+
+        let args = SuggestArgs {
+            history: PathBuf::new(),
+            limit: 10,
+            min_count: 3,
+        };
+        let path_dirs = [];
+
+        assert_eq!(
+            suggest(&args, &config, &history, &path_dirs),
+            vec![
+                Suggestion {
+                    trigger: "dcud".to_string(),
+                    snippet: "docker compose up -d".to_string(),
+                    count: 3,
+                },
+                Suggestion {
+                    trigger: "gcma".to_string(),
+                    snippet: "git checkout main".to_string(),
+                    count: 3,
+                },
+                Suggestion {
+                    trigger: "gc".to_string(),
+                    snippet: "git commit".to_string(),
+                    count: 3,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use crate::opt::TestArgs;
+use std::process;
+use zabrze::config::snippet::{Expect, SnippetTest};
+use zabrze::config::{Config, Snippet};
+use zabrze::expand::expand;
+
+pub fn run(args: &TestArgs) {
+    let (config, errors) = crate::load_config();
+    crate::print_errors(&errors);
+
+    let tests: Vec<_> = config
+        .snippets
+        .iter()
+        .enumerate()
+        .filter(|(_, snippet)| {
+            args.filter
+                .as_ref()
+                .is_none_or(|filter| snippet.display_name().contains(filter.as_str()))
+        })
+        .flat_map(|(index, snippet)| snippet.tests.iter().map(move |test| (index, snippet, test)))
+        .collect();
+
+    println!("running {} tests", tests.len());
+
+    let mut failures = Vec::new();
+    for (index, snippet, test) in &tests {
+        let title = title(snippet, test);
+        match run_test(&config, *index, test) {
+            Ok(()) => println!("test {title} ... ok"),
+            Err(messages) => {
+                println!("test {title} ... FAILED");
+                failures.push((snippet, title, messages));
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (snippet, title, messages) in &failures {
+            let file = snippet
+                .file
+                .as_ref()
+                .map(|file| format!(" ({})", file.display()))
+                .unwrap_or_default();
+            println!("\n---- {title}{file} ----");
+            for message in messages {
+                println!("{message}");
+            }
+        }
+    }
+
+    let ok = failures.is_empty() && errors.is_empty();
+    println!(
+        "\ntest result: {}. {} passed; {} failed",
+        if ok { "ok" } else { "FAILED" },
+        tests.len() - failures.len(),
+        failures.len()
+    );
+
+    if !ok {
+        process::exit(1);
+    }
+}
+
+/// Returns `name: "lbuffer|rbuffer"`, where `|` is the cursor.
+fn title(snippet: &Snippet, test: &SnippetTest) -> String {
+    let buffer = if test.rbuffer.is_empty() {
+        test.lbuffer.clone()
+    } else {
+        format!("{}|{}", test.lbuffer, test.rbuffer)
+    };
+    format!("{}: {:?}", snippet.display_name(), buffer)
+}
+
+/// Expands the buffer of `test` and compares the result with its expectations.
+///
+/// `index` is the index of the tested snippet in [`Config::snippets`].
+/// Conditions (`if`) are assumed to hold for it and to fail for the other snippets. Snippets are not evaluated.
+fn run_test(config: &Config, index: usize, test: &SnippetTest) -> Result<(), Vec<String>> {
+    let buffer = format!("{}{}", test.lbuffer, test.rbuffer);
+    let cursor = test.lbuffer.len();
+    let result = expand(config, &buffer, cursor, test.key);
+
+    if let Some(error) = result.errors.iter().find(|e| e.index == index) {
+        return Err(vec![error.to_string()]);
+    }
+
+    let expansion = result
+        .expansions
+        .iter()
+        .find(|e| e.index == index || e.condition.is_none());
+
+    match (test.expect.unwrap_or(Expect::Matched), expansion) {
+        (Expect::Unmatched, Some(e)) if e.index == index => {
+            let (lbuffer, rbuffer) = e.apply(&buffer, cursor);
+            Err(vec![format!(
+                "expected unmatched, but expanded to {:?}",
+                format!("{lbuffer}|{rbuffer}")
+            )])
+        }
+        (Expect::Unmatched, _) => Ok(()),
+        (Expect::Matched, None) => Err(vec![
+            "expected matched, but nothing was expanded".to_string(),
+        ]),
+        (Expect::Matched, Some(e)) if e.index != index => Err(vec![format!(
+            "expected matched, but '{}' was expanded",
+            e.name
+        )]),
+        (Expect::Matched, Some(e)) => {
+            let (lbuffer, rbuffer) = e.apply(&buffer, cursor);
+            let mut messages = Vec::new();
+            if let Some(expected) = &test.expect_lbuffer
+                && *expected != lbuffer
+            {
+                messages.push(format!(
+                    "expected lbuffer: {expected:?}\n  actual lbuffer: {lbuffer:?}"
+                ));
+            }
+            if let Some(expected) = &test.expect_rbuffer
+                && *expected != rbuffer
+            {
+                messages.push(format!(
+                    "expected rbuffer: {expected:?}\n  actual rbuffer: {rbuffer:?}"
+                ));
+            }
+
+            if messages.is_empty() {
+                Ok(())
+            } else {
+                Err(messages)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_test() {
+        let config = Config::load_from_str(
+            r#"
+            [[snippets]]
+            name = "git"
+            trigger = "g"
+            snippet = "git"
+
+            [[snippets.tests]]
+            lbuffer = "g"
+            expect-lbuffer = "git"
+
+            [[snippets.tests]]
+            lbuffer = "echo g"
+            expect = "unmatched"
+
+            [[snippets.tests]]
+            lbuffer = "g"
+            expect-lbuffer = "git "
+
+            [[snippets.tests]]
+            lbuffer = "g"
+            expect = "unmatched"
+
+            [[snippets]]
+            name = "git commit"
+            trigger = "c"
+            snippet = "commit -m '{}'"
+            global = true
+            context = "^git "
+
+            [[snippets.tests]]
+            lbuffer = "git c"
+            rbuffer = " --amend"
+            expect-lbuffer = "git commit -m '"
+            expect-rbuffer = "' --amend"
+
+            [[snippets.tests]]
+            lbuffer = "c"
+
+            [[snippets]]
+            name = "home"
+            trigger = "home"
+            snippet = "$HOME"
+            if = "[[ -n $HOME ]]"
+            evaluate = true
+
+            [[snippets.tests]]
+            lbuffer = "home"
+            expect-lbuffer = "$HOME"
+
+            [[snippets]]
+            name = "shadowed"
+            trigger = "g"
+            snippet = "git status"
+
+            [[snippets.tests]]
+            lbuffer = "g"
+
+            [[snippets]]
+            name = "invalid"
+            trigger = "x"
+            snippet = "x"
+            context = "("
+
+            [[snippets.tests]]
+            lbuffer = "x"
+            "#,
+        )
+        .unwrap();
+
+        let expected: &[Result<(), Vec<&str>>] = &[
+            Ok(()),
+            Ok(()),
+            Err(vec![
+                "expected lbuffer: \"git \"\n  actual lbuffer: \"git\"",
+            ]),
+            Err(vec!["expected unmatched, but expanded to \"git|\""]),
+            Ok(()),
+            Err(vec!["expected matched, but nothing was expanded"]),
+            Ok(()),
+            Err(vec!["expected matched, but 'git' was expanded"]),
+            Err(vec![
+                "snippet 'invalid': invalid regex: regex parse error:\n    (\n    ^\nerror: unclosed group",
+            ]),
+        ];
+
+        let tests: Vec<_> = config
+            .snippets
+            .iter()
+            .enumerate()
+            .flat_map(|(index, snippet)| {
+                snippet.tests.iter().map(move |test| (index, snippet, test))
+            })
+            .collect();
+        assert_eq!(tests.len(), expected.len());
+
+        for ((index, snippet, test), expected) in tests.iter().zip(expected) {
+            let actual = run_test(&config, *index, test);
+            let expected = expected
+                .clone()
+                .map_err(|messages| messages.iter().map(ToString::to_string).collect());
+
+            assert_eq!(actual, expected, "{}", title(snippet, test));
+        }
+    }
+}
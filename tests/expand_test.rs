@@ -1,8 +1,14 @@
+#![allow(clippy::needless_borrows_for_generic_args)]
+
 mod helpers;
 
+use std::env;
+use std::fs::{self, File};
 use std::path::Path;
+use std::process;
+use std::time::{Duration, SystemTime};
 
-use crate::helpers::{cli, run_command, run_command_outputs, zsh};
+use crate::helpers::{bash, cli, run_command, run_command_outputs, zsh};
 
 #[derive(Debug)]
 enum TestResult<'a> {
@@ -71,6 +77,60 @@ EOF
     }
 }
 
+fn run_bash_test(
+    config_dirname: &str,
+    (line, point): (&str, usize),
+    expected: (&str, usize, &str),
+) {
+    let config_dir = Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("testdata")
+        .join(config_dirname);
+
+    let init = run_command(cli().args(["init", "--shell", "bash"]));
+    let stdout = run_command(
+        cli()
+            .args(["expand", "--shell", "bash", "--buffer", line])
+            .args(["--cursor", &point.to_string()])
+            .env("ZABRZE_CONFIG_HOME", config_dir),
+    );
+
+    let cmd = format!(
+        r#"
+        {init}
+
+        f() {{
+          :
+          {stdout}
+        }}
+        f
+
+        cat <<EOF
+READLINE_LINE=$READLINE_LINE
+READLINE_POINT=$READLINE_POINT
+__zabrze_has_placeholder=$__zabrze_has_placeholder
+EOF
+        "#
+    );
+
+    let (result_stdout, _) = run_command_outputs(bash().args(["-c", &cmd]).envs([
+        ("READLINE_LINE", line),
+        ("READLINE_POINT", &point.to_string()),
+        ("EDITOR", "vim"),
+        ("ZABRZE_TEST", "1"),
+        ("LANG", "C.UTF-8"),
+    ]));
+
+    let (expected_line, expected_point, expected_placeholder) = expected;
+    assert_eq!(
+        result_stdout,
+        format!(
+            "READLINE_LINE={expected_line}\nREADLINE_POINT={expected_point}\n__zabrze_has_placeholder={expected_placeholder}\n"
+        )
+    );
+}
+
 #[test]
 fn test_empty() {
     let config_dirname = "empty";
@@ -108,12 +168,13 @@ fn test_basic_toml() {
             placeholder: "",
         },
     );
+    run_test(config_dirname, ("g", "add"), TestResult::Unmatched);
     run_test(
         config_dirname,
-        ("g", "add"),
+        ("", "g add"),
         TestResult::Matched {
             lbuffer: "git",
-            rbuffer: "add",
+            rbuffer: " add",
             placeholder: "",
         },
     );
@@ -176,10 +237,85 @@ fn test_basic_toml() {
         ("false || echo g", ""),
         TestResult::Unmatched,
     );
+    run_test(
+        config_dirname,
+        ("sudo g", ""),
+        TestResult::Matched {
+            lbuffer: "sudo git",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("FOO=1 nohup nice g", ""),
+        TestResult::Matched {
+            lbuffer: "FOO=1 nohup nice git",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(config_dirname, ("echo 'a;b' g", ""), TestResult::Unmatched);
+    run_test(
+        config_dirname,
+        ("echo \"x|y\" g", ""),
+        TestResult::Unmatched,
+    );
+    run_test(
+        config_dirname,
+        ("if true; then g", ""),
+        TestResult::Matched {
+            lbuffer: "if true; then git",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("echo \"$(g", ""),
+        TestResult::Matched {
+            lbuffer: "echo \"$(git",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
     run_test(config_dirname, ("G", ""), TestResult::Unmatched);
     run_test(config_dirname, ("gg", ""), TestResult::Unmatched);
 }
 
+#[test]
+fn test_precommands() {
+    let config_dirname = "precommands";
+    run_test(
+        config_dirname,
+        ("watch -n1 g", ""),
+        TestResult::Matched {
+            lbuffer: "watch -n1 git",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("A=1 sudo g", ""),
+        TestResult::Matched {
+            lbuffer: "A=1 sudo git",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("sudo -u root g", ""),
+        TestResult::Matched {
+            lbuffer: "sudo -u root git",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(config_dirname, ("nohup g", ""), TestResult::Unmatched);
+}
+
 #[test]
 fn test_global() {
     let config_dirname = "global";
@@ -201,6 +337,15 @@ fn test_global() {
             placeholder: "",
         },
     );
+    run_test(
+        config_dirname,
+        ("echo 'x|y' null", ""),
+        TestResult::Matched {
+            lbuffer: "echo 'x|y' >/dev/null 2>&1",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
 }
 
 #[test]
@@ -263,6 +408,66 @@ fn test_context() {
     );
 }
 
+#[test]
+fn test_argv() {
+    let config_dirname = "argv";
+    run_test(
+        config_dirname,
+        ("git c", ""),
+        TestResult::Matched {
+            lbuffer: "git commit",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("sudo git c", ""),
+        TestResult::Matched {
+            lbuffer: "sudo git commit",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(config_dirname, ("git log c", ""), TestResult::Unmatched);
+    run_test(config_dirname, ("c", ""), TestResult::Unmatched);
+    run_test(
+        config_dirname,
+        ("kubectl get pods -n ks", ""),
+        TestResult::Matched {
+            lbuffer: "kubectl get pods -n kube-system",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("kubectl get ks", ""),
+        TestResult::Unmatched,
+    );
+    run_test(config_dirname, ("echo -n ks", ""), TestResult::Unmatched);
+    run_test(config_dirname, ("git H", ""), TestResult::Unmatched);
+    run_test(
+        config_dirname,
+        ("git show H", ""),
+        TestResult::Matched {
+            lbuffer: "git show HEAD",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("git reset --hard H", ""),
+        TestResult::Matched {
+            lbuffer: "git reset --hard HEAD",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(config_dirname, ("git a b c H", ""), TestResult::Unmatched);
+}
+
 #[test]
 fn test_evaluate() {
     let config_dirname = "evaluate";
@@ -459,6 +664,51 @@ fn test_action() {
             placeholder: "",
         },
     );
+    run_test(
+        config_dirname,
+        ("cat a.json", ""),
+        TestResult::Matched {
+            lbuffer: "jq . a.json",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("less a.txt", " | cat"),
+        TestResult::Matched {
+            lbuffer: "vim -c '",
+            rbuffer: "' a.txt | cat",
+            placeholder: "1",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("echo; docker", ""),
+        TestResult::Matched {
+            lbuffer: "echo; sudo docker",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("tail", " a.log"),
+        TestResult::Matched {
+            lbuffer: "tail -f",
+            rbuffer: " a.log",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("echo a BYE", " b"),
+        TestResult::Matched {
+            lbuffer: "exit",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
 }
 
 #[test]
@@ -491,6 +741,124 @@ fn test_capture() {
             placeholder: "",
         },
     );
+    run_test(
+        config_dirname,
+        ("kubectl -n kube-system pods", ""),
+        TestResult::Matched {
+            lbuffer: "kubectl -n kube-system get pods -n kube-system",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("$HOME.pdf", ""),
+        TestResult::Matched {
+            lbuffer: "open '$HOME.pdf'",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("it's/", ""),
+        TestResult::Matched {
+            lbuffer: r"cd 'it'\''s'",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+}
+
+#[test]
+fn test_template() {
+    let config_dirname = "template";
+    run_test(
+        config_dirname,
+        ("cat a | .2", ""),
+        TestResult::Matched {
+            lbuffer: "cat a | awk '{ print $2 }'",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("$HOME/", ""),
+        TestResult::Matched {
+            lbuffer: "cd '$HOME'",
+            rbuffer: "",
+            placeholder: "",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("git B", ""),
+        TestResult::Matched {
+            lbuffer: "git checkout -b me/",
+            rbuffer: "",
+            placeholder: "1",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("git -c user.name=Alice B", ""),
+        TestResult::Matched {
+            lbuffer: "git -c user.name=Alice checkout -b alice/",
+            rbuffer: "",
+            placeholder: "1",
+        },
+    );
+}
+
+#[test]
+fn test_cache() {
+    let config_dir = Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("testdata")
+        .join("cache");
+    let cache_dir = env::temp_dir().join(format!("zabrze-test-cache-{}", process::id()));
+    let _ = fs::remove_dir_all(&cache_dir);
+
+    // Expands `lbuffer` in a shell where the snippet evaluates to `value`, and returns $LBUFFER
+    let expand = |lbuffer: &str, session: &str, value: &str| {
+        let stdout = run_command(
+            cli()
+                .args(["expand", "--lbuffer", lbuffer, "--rbuffer", ""])
+                .args(["--session", session])
+                .env("ZABRZE_CONFIG_HOME", &config_dir)
+                .env("ZABRZE_CACHE_HOME", &cache_dir),
+        );
+
+        let cmd = format!("{stdout}\nprint -r -- \"$LBUFFER\"");
+        run_command(
+            zsh()
+                .args(["-c", &cmd])
+                .envs([("LBUFFER", lbuffer), ("ZABRZE_TEST_VALUE", value)]),
+        )
+    };
+
+    // The evaluated text is stored and reused until it expires
+    assert_eq!(expand("ttl", "1", "A"), "A\n");
+    assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 1);
+    assert_eq!(expand("ttl", "1", "B"), "A\n");
+    for entry in fs::read_dir(&cache_dir).unwrap() {
+        let file = File::options()
+            .write(true)
+            .open(entry.unwrap().path())
+            .unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+    }
+    assert_eq!(expand("ttl", "1", "C"), "C\n");
+
+    // Session caches are not shared with other sessions
+    assert_eq!(expand("session", "1", "A"), "A\n");
+    assert_eq!(expand("session", "1", "B"), "A\n");
+    assert_eq!(expand("session", "2", "C"), "C\n");
+
+    fs::remove_dir_all(&cache_dir).unwrap();
 }
 
 #[test]
@@ -534,6 +902,225 @@ fn test_multi_files() {
     );
 }
 
+#[test]
+fn test_recursive() {
+    let config_dirname = "recursive";
+    run_test(
+        config_dirname,
+        ("gcm", ""),
+        TestResult::Matched {
+            lbuffer: "git commit -m '",
+            rbuffer: "'",
+            placeholder: "1",
+        },
+    );
+    run_test(
+        config_dirname,
+        ("echo a; gcm", " --amend"),
+        TestResult::Matched {
+            lbuffer: "echo a; git commit -m '",
+            rbuffer: "' --amend",
+            placeholder: "1",
+        },
+    );
+    run_test(config_dirname, ("foo", ""), TestResult::Unmatched);
+}
+
+#[test]
+fn test_expand_line() {
+    let config_dir = Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("testdata")
+        .join("expand_line");
+
+    let expand_line = |line: &str| {
+        run_command(
+            cli()
+                .args(["expand-line", "--", line])
+                .env("ZABRZE_CONFIG_HOME", &config_dir),
+        )
+    };
+
+    assert_eq!(expand_line(""), "\n");
+    assert_eq!(expand_line("g cm"), "git commit -m ''\n");
+    assert_eq!(
+        expand_line("g add . && g cm; g push null"),
+        "git add . && git commit -m ''; git push >/dev/null\n"
+    );
+    assert_eq!(expand_line("echo g | g log"), "echo g | git log\n");
+    assert_eq!(
+        expand_line("echo 'g' \"$(g log)\""),
+        "echo 'g' \"$(git log)\"\n"
+    );
+    assert_eq!(expand_line("home"), "home\n");
+}
+
+#[test]
+fn test_json() {
+    let expand_json = |config_dirname: &str, buffer: &str, cursor: usize| {
+        let config_dir = Path::new(file!())
+            .parent()
+            .unwrap()
+            .join("testdata")
+            .join(config_dirname);
+
+        run_command(
+            cli()
+                .args(["expand", "--format", "json", "--buffer", buffer])
+                .args(["--cursor", &cursor.to_string()])
+                .env("ZABRZE_CONFIG_HOME", config_dir),
+        )
+    };
+
+    assert_eq!(
+        expand_json("expand_line", "echo", 4),
+        r#"{"command":"echo","last_arg":"echo","expansions":[]}"#.to_string() + "\n"
+    );
+    assert_eq!(
+        expand_json("expand_line", "git cm", 6),
+        r#"{"command":"git cm","last_arg":"cm","expansions":[{"replacing_range":{"start":4,"end":6},"left_snippet":"commit -m '","right_snippet":"'","condition":null,"variables":[],"evaluate":false,"has_placeholder":true,"abort_on_error":false,"quote_captures":false,"cache":null,"name":"git commit -m ''","file":"tests/testdata/expand_line/config.toml"}]}"#.to_string() + "\n"
+    );
+    assert_eq!(
+        expand_json("conditional", "cond3", 5),
+        r#"{"command":"cond3","last_arg":"cond3","expansions":[{"replacing_range":{"start":0,"end":5},"left_snippet":"TRUE","right_snippet":"","condition":"(( ZABRZE_TEST == 0 ))","variables":[],"evaluate":false,"has_placeholder":false,"abort_on_error":false,"quote_captures":false,"cache":null,"name":"TRUE","file":"tests/testdata/conditional/config.toml"},{"replacing_range":{"start":0,"end":5},"left_snippet":"FALLBACK","right_snippet":"","condition":null,"variables":[],"evaluate":false,"has_placeholder":false,"abort_on_error":false,"quote_captures":false,"cache":null,"name":"FALLBACK","file":"tests/testdata/conditional/config.toml"}]}"#.to_string() + "\n"
+    );
+    // The cursor and the range are indices of characters
+    assert_eq!(
+        expand_json("expand_line", "echo é; g", 9),
+        r#"{"command":"g","last_arg":"g","expansions":[{"replacing_range":{"start":8,"end":9},"left_snippet":"git","right_snippet":"","condition":null,"variables":[],"evaluate":false,"has_placeholder":false,"abort_on_error":false,"quote_captures":false,"cache":null,"name":"git","file":"tests/testdata/expand_line/config.toml"}]}"#.to_string() + "\n"
+    );
+}
+
+#[test]
+fn test_error_output() {
+    let config_dir = Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("testdata")
+        .join("invalid");
+
+    let (stdout, stderr) = run_command_outputs(
+        cli()
+            .args(["expand", "--buffer", "x", "--cursor", "1"])
+            .env("ZABRZE_CONFIG_HOME", &config_dir),
+    );
+    assert!(!stdout.contains("__zabrze_error="));
+    assert!(stdout.contains("__zabrze_snippet=valid;"));
+    assert!(stderr.contains("snippet 'invalid context': invalid regex"));
+
+    let stdout = run_command(
+        cli()
+            .args(["expand", "--buffer", "x", "--cursor", "1"])
+            .args(["--error-output", "variable"])
+            .env("ZABRZE_CONFIG_HOME", &config_dir),
+    );
+    assert!(stdout.starts_with("__zabrze_error='snippet '\\''invalid context'\\'': invalid regex"));
+    assert!(stdout.contains("__zabrze_snippet=valid;"));
+
+    // expand-line and list print their output as shell code
+    let stdout = run_command(
+        cli()
+            .args([
+                "list",
+                "--format",
+                "completion",
+                "--error-output",
+                "variable",
+            ])
+            .env("ZABRZE_CONFIG_HOME", &config_dir),
+    );
+    assert_eq!(stdout, "__zabrze_output='x:invalid context\nx:valid';\n");
+
+    let config_dir = config_dir.with_file_name("recursive");
+    let stdout = run_command(
+        cli()
+            .args(["expand-line", "--error-output", "variable", "--", "g"])
+            .env("ZABRZE_CONFIG_HOME", &config_dir),
+    );
+    assert_eq!(stdout, "__zabrze_output=git;\n");
+
+    let output = cli()
+        .args(["expand-line", "--error-output", "variable", "--", "foo"])
+        .env("ZABRZE_CONFIG_HOME", &config_dir)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "__zabrze_error='recursive expansion cycle: foo -> bar -> foo';\n"
+    );
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
+
+#[test]
+fn test_snippet_tests() {
+    let config_dir = Path::new(file!())
+        .parent()
+        .unwrap()
+        .join("testdata")
+        .join("snippet_tests");
+
+    let output = cli()
+        .arg("test")
+        .env("ZABRZE_CONFIG_HOME", &config_dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!output.status.success());
+    assert_eq!(
+        stdout,
+        r#"running 4 tests
+test git: "g" ... ok
+test git: "echo g" ... ok
+test git commit: "git c" ... ok
+test git commit: "git c" ... FAILED
+
+failures:
+
+---- git commit: "git c" (tests/testdata/snippet_tests/config.toml) ----
+expected lbuffer: "git commit"
+  actual lbuffer: "git commit -m '"
+
+test result: FAILED. 3 passed; 1 failed
+"#
+    );
+
+    let output = cli()
+        .args(["test", "commit"])
+        .env("ZABRZE_CONFIG_HOME", &config_dir)
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.starts_with("running 2 tests\n"), "{stdout}");
+}
+
+#[test]
+fn test_bash() {
+    run_bash_test("basic_toml", ("g", 1), ("git", 3, ""));
+    run_bash_test("basic_toml", ("g  add", 1), ("git  add", 3, ""));
+    run_bash_test("basic_toml", ("echo g", 6), ("echo g", 6, ""));
+    run_bash_test("evaluate", ("view", 4), ("vim -R", 6, ""));
+    run_bash_test(
+        "evaluate",
+        ("echo ANSWER foo", 11),
+        ("echo The answer is 42 foo", 21, ""),
+    );
+    run_bash_test("evaluate", ("quotes", 6), (r#"say "hi""#, 8, ""));
+    run_bash_test("evaluate", ("dollar", 6), ("echo $HOME", 10, ""));
+    run_bash_test("evaluate", ("backslash", 9), (r"echo a\", 7, ""));
+    run_bash_test(
+        "evaluate",
+        ("inject", 6),
+        (r#"a "; echo INJECTED >&2; : ""#, 27, ""),
+    );
+    run_bash_test("conditional", ("cond1", 5), ("TRUE", 4, ""));
+    run_bash_test("conditional", ("cond2", 5), ("cond2", 5, ""));
+    run_bash_test("conditional", ("cond3", 5), ("FALLBACK", 8, ""));
+    run_bash_test("placeholder", ("[[ é", 2), ("[[  ]] é", 3, "1"));
+    run_bash_test("abort_on_error", ("success", 7), ("SUCCESS", 7, ""));
+}
+
 #[test]
 fn test_abort_on_error() {
     let config_dirname = "abort_on_error";
@@ -641,15 +1228,7 @@ fn test_legacy_yaml() {
             placeholder: "",
         },
     );
-    run_test(
-        config_dirname,
-        ("g", "add"),
-        TestResult::Matched {
-            lbuffer: "git",
-            rbuffer: "add",
-            placeholder: "",
-        },
-    );
+    run_test(config_dirname, ("g", "add"), TestResult::Unmatched);
     run_test(
         config_dirname,
         ("g", " add"),
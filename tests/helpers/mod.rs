@@ -12,6 +12,12 @@ pub fn zsh() -> Command {
     cmd
 }
 
+pub fn bash() -> Command {
+    let mut cmd = Command::new("bash");
+    cmd.env_clear();
+    cmd
+}
+
 pub fn run_command(cmd: &mut Command) -> String {
     let (stdout, stderr) = run_command_outputs(cmd);
     assert_eq!(stderr, "");
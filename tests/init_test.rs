@@ -1,12 +1,12 @@
 mod helpers;
 
-use crate::helpers::{cli, run_command, zsh};
+use crate::helpers::{bash, cli, run_command, zsh};
 
 fn run_test(testname: &str, args: &[&str]) {
     let stdout = run_command(cli().args(args));
     assert_ne!(stdout, "");
 
-    assert_eq!(run_command(zsh().args(&["-c", &stdout])), "");
+    assert_eq!(run_command(zsh().args(["-c", &stdout])), "");
     insta::assert_snapshot!(testname, stdout);
 }
 
@@ -14,4 +14,22 @@ fn run_test(testname: &str, args: &[&str]) {
 fn test_init() {
     run_test("init", &["init"]);
     run_test("init --bind-keys", &["init", "--bind-keys"]);
+    run_test("init --hint", &["init", "--hint"]);
+}
+
+#[test]
+fn test_init_bash() {
+    for (testname, args) in [
+        ("init --shell bash", &["init", "--shell", "bash"][..]),
+        (
+            "init --shell bash --bind-keys",
+            &["init", "--shell", "bash", "--bind-keys"][..],
+        ),
+    ] {
+        let stdout = run_command(cli().args(args));
+        assert_ne!(stdout, "");
+
+        assert_eq!(run_command(bash().args(["-n", "-c", &stdout])), "");
+        insta::assert_snapshot!(testname, stdout);
+    }
 }